@@ -0,0 +1,63 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Builds and validates a signed P2PKH spend.
+//!
+//! Run with `cargo run --example p2pkh`.
+
+use bitcoin::opcodes::all::*;
+use bitcoin::script::PushBytesBuf;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{absolute, transaction, Amount, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness};
+use interpreter::context::TxContext;
+use interpreter::flags::VerificationFlags;
+use interpreter::hash::{DefaultBackend, HashBackend};
+use interpreter::interpreter::{build_sighash, SigVersion};
+
+fn main() {
+    let secp = Secp256k1::new();
+    let sk = SecretKey::from_slice(&[0x01; 32]).expect("valid secret key");
+    let pubkey = PublicKey::from_secret_key(&secp, &sk).serialize();
+
+    // The output being spent, locked to the hash of `pubkey`.
+    let script_pubkey = Script::builder()
+        .push_opcode(OP_DUP)
+        .push_opcode(OP_HASH160)
+        .push_slice(DefaultBackend::hash160(&pubkey))
+        .push_opcode(OP_EQUALVERIFY)
+        .push_opcode(OP_CHECKSIG)
+        .into_script();
+    let prevouts = vec![TxOut { value: Amount::ONE_BTC, script_pubkey: script_pubkey.clone() }];
+
+    let mut tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::COINBASE_PREVOUT,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut { value: Amount::from_sat(99_990_000), script_pubkey: ScriptBuf::new() }],
+    };
+
+    // Legacy signatures commit to the script pubkey being spent.
+    let ctx = TxContext::new(tx.clone(), 0).expect("input 0 exists");
+    let msg = build_sighash(&ctx, &script_pubkey, 0x01, SigVersion::Base).expect("legacy sighash");
+    let mut sig = secp.sign_ecdsa(&msg, &sk).serialize_der().to_vec();
+    sig.push(0x01); // SIGHASH_ALL
+
+    tx.input[0].script_sig = Script::builder()
+        .push_slice(PushBytesBuf::try_from(sig).expect("signature fits in a push"))
+        .push_slice(pubkey)
+        .into_script();
+
+    let valid = interpreter::verify_transaction(&tx, &prevouts, VerificationFlags::CONSENSUS)
+        .expect("nothing in the script triggers failure");
+    println!("P2PKH spend is valid: {}", valid);
+    assert!(valid);
+}
+
+#[test]
+fn p2pkh_spend_is_valid() {
+    main()
+}
@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Provides static analysis of scripts i.e., inspecting a script
+//! without executing it.
+
+use std::collections::HashMap;
+
+use bitcoin::opcodes::all::*;
+use bitcoin::opcodes::Opcode;
+use bitcoin::script::{Instruction, Script, ScriptBuf, ScriptExt};
+
+use crate::interpreter::{self, MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE};
+
+/// Returns the data pushed after a leading `OP_RETURN`.
+///
+/// Useful for indexers reading `OP_RETURN` commitments. If there is
+/// more than one push after the `OP_RETURN` the data is concatenated.
+///
+/// # Returns
+///
+/// `None` if the script does not start with `OP_RETURN`, fails to parse,
+/// or contains anything other than pushes after the `OP_RETURN`.
+pub fn extract_op_return_data(script: &Script) -> Option<Vec<u8>> {
+    let mut instructions = script.instructions();
+    match instructions.next() {
+        Some(Ok(Instruction::Op(OP_RETURN))) => {}
+        _ => return None,
+    }
+
+    let mut data = Vec::new();
+    for ins in instructions {
+        match ins.ok()? {
+            Instruction::PushBytes(p) => data.extend_from_slice(p.as_bytes()),
+            Instruction::Op(_) => return None,
+        }
+    }
+    Some(data)
+}
+
+/// Returns `true` if `script` is a P2SH script pubkey.
+///
+/// The script must be exactly `OP_HASH160 <20 byte hash> OP_EQUAL`, a
+/// script that only starts with this pattern is not P2SH so the redeem
+/// script is not executed when spending it (BIP-16).
+pub fn is_p2sh(script: &Script) -> bool {
+    let bytes = script.as_bytes();
+    bytes.len() == 23
+        && bytes[0] == OP_HASH160.to_u8()
+        && bytes[1] == OP_PUSHBYTES_20.to_u8()
+        && bytes[22] == OP_EQUAL.to_u8()
+}
+
+/// Returns the redeem script of a P2SH spend with `script_sig`.
+///
+/// The redeem script is the last item pushed by the script sig. The
+/// script sig is not executed, verification instead takes the redeem
+/// script from the top of the stack after executing the script sig,
+/// which fails if a push exceeds the maximum element size or is not
+/// minimal when `MINIMALDATA` is set.
+///
+/// # Returns
+///
+/// `None` if `script_sig` is empty, fails to parse, or is not push only.
+pub fn extract_redeem_script(script_sig: &Script) -> Option<ScriptBuf> {
+    if !script_sig.is_push_only() {
+        return None;
+    }
+    let bytes = match script_sig.instructions().last()?.ok()? {
+        Instruction::PushBytes(p) => p.as_bytes().to_vec(),
+        Instruction::Op(OP_PUSHNUM_NEG1) => vec![0x81],
+        Instruction::Op(op) if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&op.to_u8()) => {
+            vec![op.to_u8() - OP_PUSHNUM_1.to_u8() + 1]
+        }
+        Instruction::Op(_) => return None,
+    };
+    Some(ScriptBuf::from_bytes(bytes))
+}
+
+/// Returns `true` if `script` can never be satisfied.
+///
+/// A script is unspendable if it starts with `OP_RETURN`, is too big,
+/// fails to parse, has unbalanced conditionals, or contains something
+/// that fails even in a branch that is not executed i.e., an oversized
+/// push, a disabled opcode, `OP_VERIF`, or `OP_VERNOTIF`.
+pub fn is_unspendable(script: &Script) -> bool {
+    if script.len() > MAX_SCRIPT_SIZE {
+        return true;
+    }
+
+    let mut depth = 0_usize;
+    for (i, ins) in script.instructions().enumerate() {
+        let op = match ins {
+            Ok(Instruction::PushBytes(p)) if p.len() > MAX_SCRIPT_ELEMENT_SIZE => return true,
+            Ok(Instruction::PushBytes(_)) => continue,
+            Ok(Instruction::Op(op)) => op,
+            Err(_) => return true,
+        };
+        if i == 0 && op == OP_RETURN {
+            return true;
+        }
+        if interpreter::is_disabled(op) || op == OP_VERIF || op == OP_VERNOTIF {
+            return true;
+        }
+        match op {
+            OP_IF | OP_NOTIF => depth += 1,
+            OP_ELSE if depth == 0 => return true,
+            OP_ENDIF => match depth.checked_sub(1) {
+                Some(d) => depth = d,
+                None => return true,
+            },
+            _ => {}
+        }
+    }
+    depth != 0
+}
+
+/// Returns the opcodes in `script` that the interpreter can not yet execute.
+///
+/// Each opcode is returned once, in the order it first appears. Parsing
+/// stops at the first invalid instruction.
+pub fn unsupported_opcodes_in(script: &Script) -> Vec<Opcode> {
+    let mut unsupported = Vec::new();
+    for ins in script.instructions() {
+        match ins {
+            Ok(Instruction::Op(op)) if !interpreter::is_supported(op) && !unsupported.contains(&op) => {
+                unsupported.push(op)
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    unsupported
+}
+
+/// Returns the number of times each opcode appears in `script`.
+///
+/// Pushes are counted under the push opcode used. Parsing stops at the
+/// first invalid instruction.
+pub fn opcode_histogram(script: &Script) -> HashMap<Opcode, usize> {
+    let mut histogram = HashMap::new();
+    for (pc, ins) in script.instruction_indices() {
+        if ins.is_err() {
+            break;
+        }
+        *histogram.entry(Opcode::from(script.as_bytes()[pc])).or_insert(0) += 1;
+    }
+    histogram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::script::PushBytesBuf;
+
+    #[test]
+    fn op_return_data() {
+        let script = Script::builder()
+            .push_opcode(OP_RETURN)
+            .push_slice(&[0xde, 0xad, 0xbe, 0xef])
+            .into_script();
+
+        assert_eq!(extract_op_return_data(&script), Some(vec![0xde, 0xad, 0xbe, 0xef]));
+    }
+
+    #[test]
+    fn no_op_return_data() {
+        let script = Script::builder()
+            .push_slice(&[0xde, 0xad, 0xbe, 0xef])
+            .push_opcode(OP_DROP)
+            .into_script();
+
+        assert_eq!(extract_op_return_data(&script), None);
+    }
+
+    #[test]
+    fn op_return_is_unspendable() {
+        let script = Script::builder().push_opcode(OP_RETURN).push_slice(&[0xab]).into_script();
+
+        assert!(is_unspendable(&script));
+    }
+
+    #[test]
+    fn disabled_opcode_is_unspendable() {
+        let script = Script::builder()
+            .push_slice(&[0xab])
+            .push_slice(&[0xcd])
+            .push_opcode(OP_CAT)
+            .into_script();
+
+        assert!(is_unspendable(&script));
+    }
+
+    #[test]
+    fn p2sh_template() {
+        let script = Script::builder().push_opcode(OP_HASH160).push_slice([0xab; 20]).push_opcode(OP_EQUAL).into_script();
+
+        assert!(is_p2sh(&script));
+    }
+
+    #[test]
+    fn p2sh_template_with_trailing_opcode() {
+        let script = Script::builder()
+            .push_opcode(OP_HASH160)
+            .push_slice([0xab; 20])
+            .push_opcode(OP_EQUAL)
+            .push_opcode(OP_NOP)
+            .into_script();
+
+        assert!(!is_p2sh(&script));
+    }
+
+    #[test]
+    fn p2sh_template_with_wrong_push_length() {
+        let script = Script::builder().push_opcode(OP_HASH160).push_slice([0xab; 21]).push_opcode(OP_EQUAL).into_script();
+
+        assert!(!is_p2sh(&script));
+    }
+
+    /// Returns a 2-of-2 multisig redeem script.
+    fn multisig_redeem_script() -> ScriptBuf {
+        Script::builder()
+            .push_int(2).unwrap()
+            .push_slice([0x02; 33])
+            .push_slice([0x03; 33])
+            .push_int(2).unwrap()
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script()
+    }
+
+    #[test]
+    fn extract_multisig_redeem_script() {
+        let redeem_script = multisig_redeem_script();
+        let script_sig = Script::builder()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_slice([0xab; 71])
+            .push_slice([0xcd; 71])
+            .push_slice(PushBytesBuf::try_from(redeem_script.to_bytes()).unwrap())
+            .into_script();
+
+        assert_eq!(extract_redeem_script(&script_sig), Some(redeem_script));
+    }
+
+    #[test]
+    fn extract_redeem_script_not_push_only() {
+        let script_sig = Script::builder()
+            .push_slice(PushBytesBuf::try_from(multisig_redeem_script().to_bytes()).unwrap())
+            .push_opcode(OP_DUP)
+            .into_script();
+
+        assert_eq!(extract_redeem_script(&script_sig), None);
+        assert_eq!(extract_redeem_script(Script::new()), None);
+    }
+
+    #[test]
+    fn unbalanced_conditional_is_unspendable() {
+        let script = Script::builder().push_int(1).unwrap().push_opcode(OP_IF).push_int(2).unwrap().into_script();
+
+        assert!(is_unspendable(&script));
+    }
+
+    #[test]
+    fn p2pkh_is_spendable() {
+        let script = Script::builder()
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_slice(&[0xab; 20])
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+
+        assert!(!is_unspendable(&script));
+    }
+
+    #[test]
+    fn unsupported_opcodes() {
+        let script = Script::builder()
+            .push_int(2).unwrap()
+            .push_int(3).unwrap()
+            .push_opcode(OP_ADD)
+            .push_opcode(OP_SIZE)
+            .push_opcode(OP_NEGATE)
+            .push_opcode(OP_SIZE)
+            .into_script();
+
+        assert_eq!(unsupported_opcodes_in(&script), vec![OP_SIZE, OP_NEGATE]);
+    }
+
+    #[test]
+    fn histogram() {
+        let script = Script::builder()
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_slice([0xab; 20])
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_opcode(OP_DUP)
+            .into_script();
+        let histogram = opcode_histogram(&script);
+
+        assert_eq!(histogram.len(), 4);
+        assert_eq!(histogram[&OP_DUP], 3);
+        assert_eq!(histogram[&OP_HASH160], 2);
+        assert_eq!(histogram[&OP_PUSHBYTES_20], 1);
+        assert_eq!(histogram[&OP_EQUALVERIFY], 1);
+    }
+}
@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Provides the transaction data needed by opcodes that inspect the
+//! spending transaction e.g., `OP_CHECKLOCKTIMEVERIFY`.
+
+use anyhow::{bail, Result};
+use bitcoin::{Transaction, TxIn, TxOut};
+
+/// The spending transaction and the index of the input being validated.
+pub struct TxContext {
+    /// The transaction spending the output locked by the script.
+    tx: Transaction,
+    /// The index of the input being validated.
+    input_index: usize,
+    /// The outputs spent by each input of the transaction, if known.
+    prevouts: Option<Vec<TxOut>>,
+    /// The height of the block including the transaction, if known.
+    block_height: Option<u32>,
+    /// The median time past of the block before the block including the
+    /// transaction, if known.
+    median_time_past: Option<u32>,
+    /// The height of the block including the output spent by the input
+    /// being validated, if known.
+    prevout_height: Option<u32>,
+    /// The median time past of the block before the block including the
+    /// output spent by the input being validated, if known.
+    prevout_median_time_past: Option<u32>,
+}
+
+impl TxContext {
+    /// Creates a new context for input `input_index` of `tx`.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if `input_index` is out of range.
+    pub fn new(tx: Transaction, input_index: usize) -> Result<Self> {
+        if input_index >= tx.input.len() {
+            bail!("input index {} out of range for transaction with {} inputs", input_index, tx.input.len());
+        }
+        Ok(Self {
+            tx,
+            input_index,
+            prevouts: None,
+            block_height: None,
+            median_time_past: None,
+            prevout_height: None,
+            prevout_median_time_past: None,
+        })
+    }
+
+    /// Sets the outputs spent by the transaction, one for each input.
+    ///
+    /// These are required to check taproot signatures.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there is not exactly one output per input.
+    pub fn with_prevouts(mut self, prevouts: Vec<TxOut>) -> Result<Self> {
+        if prevouts.len() != self.tx.input.len() {
+            bail!("got {} prevouts for transaction with {} inputs", prevouts.len(), self.tx.input.len());
+        }
+        self.prevouts = Some(prevouts);
+        Ok(self)
+    }
+
+    /// Sets the height of the block including the transaction.
+    ///
+    /// If set, `OP_CHECKLOCKTIMEVERIFY` with a block height lock time also
+    /// requires the transaction to be final at this height. Finality is
+    /// only checked by `OP_CHECKLOCKTIMEVERIFY`, a transaction that does
+    /// not execute it is not checked against the height.
+    ///
+    /// If the prevout height is also set, `OP_CHECKSEQUENCEVERIFY` with a
+    /// block height relative lock time requires the relative lock time of
+    /// the input to have passed at this height (BIP-68).
+    pub fn with_block_height(mut self, height: u32) -> Self {
+        self.block_height = Some(height);
+        self
+    }
+
+    /// Sets the median time past used to check time based lock times.
+    ///
+    /// If set, `OP_CHECKLOCKTIMEVERIFY` with a time lock time also
+    /// requires the transaction to be final at this time. As with the
+    /// block height, a transaction that does not execute
+    /// `OP_CHECKLOCKTIMEVERIFY` is not checked.
+    ///
+    /// If the prevout median time past is also set,
+    /// `OP_CHECKSEQUENCEVERIFY` with a time relative lock time requires the
+    /// relative lock time of the input to have passed at this time (BIP-68).
+    pub fn with_median_time_past(mut self, median_time_past: u32) -> Self {
+        self.median_time_past = Some(median_time_past);
+        self
+    }
+
+    /// Sets the height of the block including the output spent by the
+    /// input being validated, see [`Self::with_block_height`].
+    pub fn with_prevout_height(mut self, height: u32) -> Self {
+        self.prevout_height = Some(height);
+        self
+    }
+
+    /// Sets the median time past of the block before the block including
+    /// the output spent by the input being validated, see
+    /// [`Self::with_median_time_past`].
+    pub fn with_prevout_median_time_past(mut self, median_time_past: u32) -> Self {
+        self.prevout_median_time_past = Some(median_time_past);
+        self
+    }
+
+    /// Returns the height of the block including the transaction, if known.
+    pub fn block_height(&self) -> Option<u32> {
+        self.block_height
+    }
+
+    /// Returns the median time past, if known.
+    pub fn median_time_past(&self) -> Option<u32> {
+        self.median_time_past
+    }
+
+    /// Returns the height of the block including the spent output, if known.
+    pub fn prevout_height(&self) -> Option<u32> {
+        self.prevout_height
+    }
+
+    /// Returns the median time past for the spent output, if known.
+    pub fn prevout_median_time_past(&self) -> Option<u32> {
+        self.prevout_median_time_past
+    }
+
+    /// Returns the spending transaction.
+    pub fn tx(&self) -> &Transaction {
+        &self.tx
+    }
+
+    /// Returns the index of the input being validated.
+    pub fn input_index(&self) -> usize {
+        self.input_index
+    }
+
+    /// Returns the input being validated.
+    pub fn input(&self) -> &TxIn {
+        &self.tx.input[self.input_index]
+    }
+
+    /// Returns the outputs spent by the transaction, if known.
+    pub fn prevouts(&self) -> Option<&[TxOut]> {
+        self.prevouts.as_deref()
+    }
+}
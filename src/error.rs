@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Provides the errors returned by script execution.
+//!
+//! The variants map to Bitcoin Core's `SCRIPT_ERR_*` codes so that
+//! failures can be matched against the expected codes in Core's tests.
+
+use core::fmt;
+
+use bitcoin::opcodes::Opcode;
+
+/// An error triggered by something in the script during execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+    /// The stack does not hold enough items for the operation, or the
+    /// `OP_PICK` or `OP_ROLL` index is negative or out of range (`INVALID_STACK_OPERATION`).
+    InvalidStackOperation,
+    /// `OP_FROMALTSTACK` executed with an empty alt stack (`INVALID_ALTSTACK_OPERATION`).
+    InvalidAltstackOperation,
+    /// Executing `opcode` needed more items than are on the stack.
+    StackUnderflow {
+        /// The opcode that triggered the underflow.
+        opcode: Opcode,
+    },
+    /// `OP_RETURN` was executed (`OP_RETURN`).
+    EarlyReturn,
+    /// The script exceeds the maximum script size (`SCRIPT_SIZE`).
+    ScriptSize,
+    /// The stack and alt stack hold more than the maximum number of items (`STACK_SIZE`).
+    StackSize,
+    /// The `OP_CHECKMULTISIG` signature count is negative or greater than the public key count (`SIG_COUNT`).
+    SigCount,
+    /// The `OP_CHECKMULTISIG` public key count is negative or exceeds the limit (`PUBKEY_COUNT`).
+    PubkeyCount,
+    /// `OP_VERIFY` found a false top stack item (`VERIFY`).
+    Verify,
+    /// The items compared by `OP_EQUALVERIFY` are not equal (`EQUALVERIFY`).
+    EqualVerify,
+    /// The signatures checked by `OP_CHECKMULTISIGVERIFY` are not valid (`CHECKMULTISIGVERIFY`).
+    CheckMultisigVerify,
+    /// The signature checked by `OP_CHECKSIGVERIFY` is not valid (`CHECKSIGVERIFY`).
+    CheckSigVerify,
+    /// The script contains an opcode that is invalid even when not executed,
+    /// or that is not valid for the signature version (`BAD_OPCODE`).
+    BadOpcode,
+    /// A number is too long, or not minimally encoded, to be used as an
+    /// operand (`UNKNOWN_ERROR` in Core, from `scriptnum_error`).
+    InvalidScriptInt,
+    /// The interpreter does not yet implement the opcode.
+    UnsupportedOpcode(Opcode),
+    /// The script evaluated without error but finished with an empty or false top stack item (`EVAL_FALSE`).
+    EvalFalse,
+    /// More than one item remains on the stack after execution (`CLEANSTACK`).
+    CleanStack,
+    /// An `OP_ELSE` or `OP_ENDIF` without an `OP_IF`, or an `OP_IF` without an `OP_ENDIF` (`UNBALANCED_CONDITIONAL`).
+    UnbalancedConditional,
+    /// The argument to `OP_IF` or `OP_NOTIF` is not empty or `[0x01]` (`MINIMALIF`).
+    MinimalIf,
+    /// The script contains more than the maximum number of non-push opcodes (`OP_COUNT`).
+    OpCount,
+    /// The script contains an opcode disabled in 2010 (`DISABLED_OPCODE`).
+    DisabledOpcode,
+    /// Data is pushed without using the smallest possible push opcode (`MINIMALDATA`).
+    MinimalData,
+    /// A public key in a segwit v0 script is not compressed (`WITNESS_PUBKEYTYPE`).
+    WitnessPubkeyType,
+    /// A public key in a tapscript is empty (`PUBKEYTYPE`).
+    PubkeyType,
+    /// A tapscript public key is of an unknown type and they are discouraged
+    /// (`DISCOURAGE_UPGRADABLE_PUBKEYTYPE`).
+    DiscourageUpgradablePubkeyType,
+    /// A non-empty Schnorr signature is invalid (`SCHNORR_SIG`).
+    SchnorrSig,
+    /// A Schnorr signature is not 64 or 65 bytes (`SCHNORR_SIG_SIZE`).
+    SchnorrSigSize,
+    /// A Schnorr signature has an invalid sighash type (`SCHNORR_SIG_HASHTYPE`).
+    SchnorrSigHashtype,
+    /// `OP_CHECKMULTISIG` or `OP_CHECKMULTISIGVERIFY` executed in a tapscript (`TAPSCRIPT_CHECKMULTISIG`).
+    TapscriptCheckMultisig,
+    /// The tapscript validation budget is exceeded (`TAPSCRIPT_VALIDATION_WEIGHT`).
+    TapscriptValidationWeight,
+    /// The tapscript contains an `OP_SUCCESSx` opcode and they are discouraged (`DISCOURAGE_OP_SUCCESS`).
+    DiscourageOpSuccess,
+    /// The S value of a signature is greater than half the curve order (`SIG_HIGH_S`).
+    SigHighS,
+    /// The script sig contains a non-push opcode (`SIG_PUSHONLY`).
+    SigPushOnly,
+    /// The dummy element consumed by `OP_CHECKMULTISIG` is not empty (`SIG_NULLDUMMY`).
+    SigNullDummy,
+    /// The lock time or relative lock time on top of the stack is negative (`NEGATIVE_LOCKTIME`).
+    NegativeLocktime,
+    /// The lock time or relative lock time on top of the stack is not satisfied by the spending
+    /// transaction (`UNSATISFIED_LOCKTIME`).
+    UnsatisfiedLocktime,
+    /// A pushed or witness stack element exceeds the maximum element size (`PUSH_SIZE`).
+    PushSize,
+    /// The P2WSH witness script exceeds the maximum standard size.
+    WitnessScriptSize,
+    /// A witness program has an invalid length for its version (`WITNESS_PROGRAM_WRONG_LENGTH`).
+    WitnessProgramWrongLength,
+    /// A witness program is spent with an empty witness (`WITNESS_PROGRAM_WITNESS_EMPTY`).
+    WitnessProgramWitnessEmpty,
+    /// The witness does not match the witness program (`WITNESS_PROGRAM_MISMATCH`).
+    WitnessProgramMismatch,
+    /// The script sig of a witness program spend is not empty, or for P2SH
+    /// wrapped segwit does not only push the redeem script (`WITNESS_MALLEATED`,
+    /// `WITNESS_MALLEATED_P2SH`).
+    WitnessMalleated,
+    /// A spend that is not of a witness program has a witness (`WITNESS_UNEXPECTED`).
+    WitnessUnexpected,
+    /// A witness program has an unknown version and they are discouraged
+    /// (`DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM`).
+    DiscourageUpgradableWitnessProgram,
+    /// The interpreter does not yet support spending the witness program.
+    UnsupportedWitnessSpend,
+    /// The script checks the spending transaction but there is no transaction context.
+    MissingTxContext,
+    /// The script checks a signature that commits to the spent outputs but
+    /// the transaction context does not include them.
+    MissingPrevouts,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ScriptError::*;
+
+        match *self {
+            InvalidStackOperation => f.write_str("operation not valid with the current stack size"),
+            InvalidAltstackOperation => f.write_str("operation not valid with the current altstack size"),
+            StackUnderflow { opcode } => write!(f, "stack underflow executing {}", opcode),
+            EarlyReturn => f.write_str("OP_RETURN was encountered"),
+            ScriptSize => f.write_str("script is too big"),
+            StackSize => f.write_str("stack size limit exceeded"),
+            SigCount => f.write_str("signature count negative or greater than pubkey count"),
+            PubkeyCount => f.write_str("pubkey count negative or limit exceeded"),
+            Verify => f.write_str("script failed an OP_VERIFY operation"),
+            EqualVerify => f.write_str("script failed an OP_EQUALVERIFY operation"),
+            CheckMultisigVerify => f.write_str("script failed an OP_CHECKMULTISIGVERIFY operation"),
+            CheckSigVerify => f.write_str("script failed an OP_CHECKSIGVERIFY operation"),
+            BadOpcode => f.write_str("opcode missing or not understood"),
+            InvalidScriptInt => f.write_str("invalid script number"),
+            UnsupportedOpcode(op) => write!(f, "opcode not yet supported: {}", op),
+            EvalFalse => f.write_str("script evaluated without error but finished with a false/empty top stack element"),
+            CleanStack => f.write_str("stack size must be exactly one after execution"),
+            UnbalancedConditional => f.write_str("invalid OP_IF construction"),
+            MinimalIf => f.write_str("OP_IF/NOTIF argument must be minimal"),
+            OpCount => f.write_str("operation limit exceeded"),
+            DisabledOpcode => f.write_str("attempted to use a disabled opcode"),
+            MinimalData => f.write_str("data push larger than necessary"),
+            WitnessPubkeyType => f.write_str("using non-compressed public key"),
+            TapscriptValidationWeight => f.write_str("too much signature validation relative to witness weight"),
+            PubkeyType => f.write_str("public key is neither compressed or uncompressed"),
+            DiscourageUpgradablePubkeyType => f.write_str("public key version reserved for soft-fork upgrades"),
+            SchnorrSig => f.write_str("invalid Schnorr signature"),
+            SchnorrSigSize => f.write_str("invalid Schnorr signature size"),
+            SchnorrSigHashtype => f.write_str("invalid Schnorr signature hash type"),
+            TapscriptCheckMultisig => f.write_str("OP_CHECKMULTISIG(VERIFY) is not available in tapscript"),
+            DiscourageOpSuccess => f.write_str("OP_SUCCESSx reserved for soft-fork upgrades"),
+            SigHighS => f.write_str("non-canonical signature: S value is unnecessarily high"),
+            SigPushOnly => f.write_str("only push operators allowed in signatures"),
+            SigNullDummy => f.write_str("dummy OP_CHECKMULTISIG argument must be zero"),
+            NegativeLocktime => f.write_str("negative locktime"),
+            UnsatisfiedLocktime => f.write_str("locktime requirement not satisfied"),
+            PushSize => f.write_str("push value size limit exceeded"),
+            WitnessScriptSize => f.write_str("witness script size limit exceeded"),
+            WitnessProgramWrongLength => f.write_str("witness program has incorrect length"),
+            WitnessProgramWitnessEmpty => f.write_str("witness program was passed an empty witness"),
+            WitnessProgramMismatch => f.write_str("witness program hash mismatch"),
+            WitnessMalleated => f.write_str("witness requires empty scriptSig"),
+            WitnessUnexpected => f.write_str("witness provided for non-witness script"),
+            DiscourageUpgradableWitnessProgram => f.write_str("witness version reserved for soft-fork upgrades"),
+            UnsupportedWitnessSpend => f.write_str("witness program spend not yet supported"),
+            MissingTxContext => f.write_str("script requires a transaction context"),
+            MissingPrevouts => f.write_str("script requires the outputs spent by the transaction"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
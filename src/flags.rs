@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Provides the script verification flags.
+//!
+//! The flags mirror the `SCRIPT_VERIFY_*` flags used by Bitcoin Core,
+//! including the bit values, so a set of flags can be converted to and
+//! from the integer representation used by Core (e.g. in `script_tests.json`).
+
+/// The mainnet block height BIP-16 (P2SH) activated at.
+pub const BIP16_HEIGHT: u32 = 173_805;
+/// The mainnet block height BIP-66 (strict DER signatures) activated at.
+pub const BIP66_HEIGHT: u32 = 363_725;
+/// The mainnet block height BIP-65 (`OP_CHECKLOCKTIMEVERIFY`) activated at.
+pub const BIP65_HEIGHT: u32 = 388_381;
+/// The mainnet block height BIP-112 (`OP_CHECKSEQUENCEVERIFY`) activated at.
+pub const CSV_HEIGHT: u32 = 419_328;
+/// The mainnet block height segwit (BIP-141 and BIP-147) activated at.
+pub const SEGWIT_HEIGHT: u32 = 481_824;
+/// The mainnet block height taproot (BIP-341 and BIP-342) activated at.
+pub const TAPROOT_HEIGHT: u32 = 709_632;
+
+/// A single script verification flag.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum VerificationFlag {
+    /// Evaluate P2SH subscripts (BIP-16).
+    P2sh = 1 << 0,
+    /// Enforce strict encoding of signatures and public keys.
+    StrictEnc = 1 << 1,
+    /// Enforce strict DER encoding of signatures (BIP-66).
+    DerSig = 1 << 2,
+    /// Enforce low S values in signatures.
+    LowS = 1 << 3,
+    /// The dummy element consumed by `OP_CHECKMULTISIG` must be empty (BIP-147).
+    NullDummy = 1 << 4,
+    /// The script sig must only contain push operations.
+    SigPushOnly = 1 << 5,
+    /// Pushes and numbers must use the smallest possible encoding.
+    MinimalData = 1 << 6,
+    /// Fail on the upgradable NOPs (`OP_NOP1`, `OP_NOP4` - `OP_NOP10`).
+    DiscourageUpgradableNops = 1 << 7,
+    /// Exactly one item must remain on the stack after execution.
+    CleanStack = 1 << 8,
+    /// Enable `OP_CHECKLOCKTIMEVERIFY` (BIP-65).
+    CheckLockTimeVerify = 1 << 9,
+    /// Enable `OP_CHECKSEQUENCEVERIFY` (BIP-112).
+    CheckSequenceVerify = 1 << 10,
+    /// Evaluate segregated witness programs (BIP-141).
+    Witness = 1 << 11,
+    /// Fail on unknown witness program versions.
+    DiscourageUpgradableWitnessProgram = 1 << 12,
+    /// The argument to `OP_IF` and `OP_NOTIF` must be empty or `[0x01]`.
+    MinimalIf = 1 << 13,
+    /// Failed signature checks require an empty signature.
+    NullFail = 1 << 14,
+    /// Public keys in segwit v0 scripts must be compressed.
+    WitnessPubkeyType = 1 << 15,
+    /// `OP_CODESEPARATOR` and `FindAndDelete` fail in non-segwit scripts.
+    ConstScriptCode = 1 << 16,
+    /// Evaluate taproot outputs (BIP-341 and BIP-342).
+    Taproot = 1 << 17,
+    /// Fail on unknown taproot leaf versions.
+    DiscourageUpgradableTaprootVersion = 1 << 18,
+    /// Fail on `OP_SUCCESSx` opcodes in tapscript.
+    DiscourageOpSuccess = 1 << 19,
+    /// Fail on unknown public key types in tapscript.
+    DiscourageUpgradablePubkeyType = 1 << 20,
+    /// P2WSH witness scripts must not exceed the maximum standard size.
+    ///
+    /// Core checks this as part of its standardness policy rather than with
+    /// a script flag, so this uses a bit Core does not.
+    StandardWitnessScriptSize = 1 << 30,
+}
+
+impl VerificationFlag {
+    /// All the verification flags, in bit order.
+    pub const ALL: [Self; 22] = [
+        Self::P2sh,
+        Self::StrictEnc,
+        Self::DerSig,
+        Self::LowS,
+        Self::NullDummy,
+        Self::SigPushOnly,
+        Self::MinimalData,
+        Self::DiscourageUpgradableNops,
+        Self::CleanStack,
+        Self::CheckLockTimeVerify,
+        Self::CheckSequenceVerify,
+        Self::Witness,
+        Self::DiscourageUpgradableWitnessProgram,
+        Self::MinimalIf,
+        Self::NullFail,
+        Self::WitnessPubkeyType,
+        Self::ConstScriptCode,
+        Self::Taproot,
+        Self::DiscourageUpgradableTaprootVersion,
+        Self::DiscourageOpSuccess,
+        Self::DiscourageUpgradablePubkeyType,
+        Self::StandardWitnessScriptSize,
+    ];
+
+    /// The verification flags enforced by this crate, in bit order.
+    ///
+    /// The other flags can be set but do not yet change how a script is
+    /// verified: `StrictEnc`, `DerSig`, `NullFail`, `ConstScriptCode`,
+    /// `DiscourageUpgradableNops`, and `DiscourageUpgradableTaprootVersion`.
+    pub const ENFORCED: [Self; 16] = [
+        Self::P2sh,
+        Self::LowS,
+        Self::NullDummy,
+        Self::SigPushOnly,
+        Self::MinimalData,
+        Self::CleanStack,
+        Self::CheckLockTimeVerify,
+        Self::CheckSequenceVerify,
+        Self::Witness,
+        Self::DiscourageUpgradableWitnessProgram,
+        Self::MinimalIf,
+        Self::WitnessPubkeyType,
+        Self::Taproot,
+        Self::DiscourageOpSuccess,
+        Self::DiscourageUpgradablePubkeyType,
+        Self::StandardWitnessScriptSize,
+    ];
+
+    /// Returns the bit used to represent this flag.
+    pub fn to_bit(self) -> u32 {
+        self as u32
+    }
+}
+
+/// A set of script verification flags.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct VerificationFlags(u32);
+
+impl VerificationFlags {
+    /// No flags set.
+    pub const NONE: Self = Self(0);
+
+    /// No flags set, for experimentation.
+    ///
+    /// The rules that are not controlled by a flag are still enforced
+    /// e.g., disabled opcodes, `OP_RETURN`, and the stack, element, and
+    /// opcode count limits.
+    pub const PERMISSIVE: Self = Self::NONE;
+
+    /// The consensus rules currently enforced on mainnet.
+    pub const CONSENSUS: Self = Self(
+        VerificationFlag::P2sh as u32
+            | VerificationFlag::DerSig as u32
+            | VerificationFlag::NullDummy as u32
+            | VerificationFlag::CheckLockTimeVerify as u32
+            | VerificationFlag::CheckSequenceVerify as u32
+            | VerificationFlag::Witness as u32
+            | VerificationFlag::Taproot as u32,
+    );
+
+    /// Returns the mandatory consensus flags for a mainnet block at `height`.
+    ///
+    /// Useful to validate historical transactions under the rules that
+    /// were active when they were mined.
+    pub fn mandatory_at_height(height: u32) -> Self {
+        let activations = [
+            (BIP16_HEIGHT, VerificationFlag::P2sh),
+            (BIP66_HEIGHT, VerificationFlag::DerSig),
+            (BIP65_HEIGHT, VerificationFlag::CheckLockTimeVerify),
+            (CSV_HEIGHT, VerificationFlag::CheckSequenceVerify),
+            (SEGWIT_HEIGHT, VerificationFlag::Witness),
+            (SEGWIT_HEIGHT, VerificationFlag::NullDummy),
+            (TAPROOT_HEIGHT, VerificationFlag::Taproot),
+        ];
+        activations
+            .iter()
+            .filter(|(activation, _)| height >= *activation)
+            .fold(Self::NONE, |flags, (_, flag)| flags.with(*flag))
+    }
+
+    /// Creates a set of flags from Bitcoin Core's integer representation.
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// Returns Bitcoin Core's integer representation of this set of flags.
+    pub fn to_bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns `true` if `flag` is set.
+    pub fn contains(self, flag: VerificationFlag) -> bool {
+        self.0 & flag.to_bit() != 0
+    }
+
+    /// Returns a copy of this set with `flag` set.
+    pub fn with(self, flag: VerificationFlag) -> Self {
+        Self(self.0 | flag.to_bit())
+    }
+
+    /// Returns a copy of this set with `flag` cleared.
+    pub fn without(self, flag: VerificationFlag) -> Self {
+        Self(self.0 & !flag.to_bit())
+    }
+}
+
+impl From<VerificationFlag> for VerificationFlags {
+    fn from(flag: VerificationFlag) -> Self {
+        Self(flag.to_bit())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mandatory_flags_by_height() {
+        assert_eq!(VerificationFlags::mandatory_at_height(0), VerificationFlags::NONE);
+        assert_eq!(VerificationFlags::mandatory_at_height(BIP16_HEIGHT - 1), VerificationFlags::NONE);
+        assert_eq!(VerificationFlags::mandatory_at_height(BIP16_HEIGHT), VerificationFlag::P2sh.into());
+        assert_eq!(VerificationFlags::mandatory_at_height(TAPROOT_HEIGHT), VerificationFlags::CONSENSUS);
+    }
+}
@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Provides the hash functions used by the hash opcodes.
+//!
+//! The implementation is selected at compile time using feature flags:
+//!
+//! - `bitcoin-hashes` (default): hash using `bitcoin_hashes`.
+//! - `rustcrypto`: hash using the RustCrypto crates, these can make use
+//!   of hardware acceleration.
+//!
+//! If both features are enabled `bitcoin-hashes` is used. All backends
+//! produce byte identical output.
+
+/// The hash functions required to execute the hash opcodes.
+///
+/// Implementations must return the standard digests, `hash160` and
+/// `hash256` are built from the other functions.
+pub trait HashBackend {
+    /// Returns the SHA-1 digest of `data`.
+    fn sha1(data: &[u8]) -> [u8; 20];
+
+    /// Returns the SHA-256 digest of `data`.
+    fn sha256(data: &[u8]) -> [u8; 32];
+
+    /// Returns the RIPEMD-160 digest of `data`.
+    fn ripemd160(data: &[u8]) -> [u8; 20];
+
+    /// Returns `RIPEMD160(SHA256(data))`.
+    fn hash160(data: &[u8]) -> [u8; 20] {
+        Self::ripemd160(&Self::sha256(data))
+    }
+
+    /// Returns `SHA256(SHA256(data))`.
+    fn hash256(data: &[u8]) -> [u8; 32] {
+        Self::sha256(&Self::sha256(data))
+    }
+}
+
+/// Returns the BIP-340 tagged hash of `msg`.
+///
+/// The tagged hash is `SHA256(SHA256(tag) || SHA256(tag) || msg)`. The
+/// interpreter uses it for the tap leaf hash, the taproot signature hash
+/// is computed by `bitcoin::sighash::SighashCache`.
+pub fn tagged_hash(tag: &str, msg: &[u8]) -> [u8; 32] {
+    let tag_hash = DefaultBackend::sha256(tag.as_bytes());
+
+    let mut data = Vec::with_capacity(64 + msg.len());
+    data.extend_from_slice(&tag_hash);
+    data.extend_from_slice(&tag_hash);
+    data.extend_from_slice(msg);
+    DefaultBackend::sha256(&data)
+}
+
+/// Returns the tap leaf hash of `script` with leaf version `leaf_version` (BIP-341).
+pub(crate) fn tap_leaf_hash(script: &[u8], leaf_version: u8) -> [u8; 32] {
+    let mut msg = Vec::with_capacity(script.len() + 10);
+    msg.push(leaf_version);
+    msg.extend_from_slice(&compact_size(script.len() as u64));
+    msg.extend_from_slice(script);
+    tagged_hash("TapLeaf", &msg)
+}
+
+/// Returns the compact size encoding of `n`.
+fn compact_size(n: u64) -> Vec<u8> {
+    match n {
+        0..=0xfc => vec![n as u8],
+        0xfd..=0xffff => [&[0xfd][..], &(n as u16).to_le_bytes()].concat(),
+        0x10000..=0xffff_ffff => [&[0xfe][..], &(n as u32).to_le_bytes()].concat(),
+        _ => [&[0xff][..], &n.to_le_bytes()].concat(),
+    }
+}
+
+/// The hash backend used by the interpreter.
+#[cfg(feature = "bitcoin-hashes")]
+pub type DefaultBackend = BitcoinHashes;
+
+/// The hash backend used by the interpreter.
+#[cfg(all(feature = "rustcrypto", not(feature = "bitcoin-hashes")))]
+pub type DefaultBackend = RustCrypto;
+
+#[cfg(not(any(feature = "bitcoin-hashes", feature = "rustcrypto")))]
+compile_error!("one of the `bitcoin-hashes` or `rustcrypto` features must be enabled");
+
+/// Hashes using `bitcoin_hashes`.
+#[cfg(feature = "bitcoin-hashes")]
+pub struct BitcoinHashes;
+
+#[cfg(feature = "bitcoin-hashes")]
+impl HashBackend for BitcoinHashes {
+    fn sha1(data: &[u8]) -> [u8; 20] {
+        bitcoin::hashes::sha1::Hash::hash(data).to_byte_array()
+    }
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        bitcoin::hashes::sha256::Hash::hash(data).to_byte_array()
+    }
+
+    fn ripemd160(data: &[u8]) -> [u8; 20] {
+        bitcoin::hashes::ripemd160::Hash::hash(data).to_byte_array()
+    }
+}
+
+/// Hashes using the RustCrypto crates.
+#[cfg(feature = "rustcrypto")]
+pub struct RustCrypto;
+
+#[cfg(feature = "rustcrypto")]
+impl HashBackend for RustCrypto {
+    fn sha1(data: &[u8]) -> [u8; 20] {
+        use sha1::Digest;
+        sha1::Sha1::digest(data).into()
+    }
+
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        use sha2::Digest;
+        sha2::Sha256::digest(data).into()
+    }
+
+    fn ripemd160(data: &[u8]) -> [u8; 20] {
+        use ripemd::Digest;
+        ripemd::Ripemd160::digest(data).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::hex_to_bytes;
+
+    #[test]
+    fn tap_leaf_tagged_hash() {
+        // Leaf from the BIP-341 wallet test vectors: leaf version, script length, script.
+        let msg = hex_to_bytes("c02220d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8ac");
+        let want = hex_to_bytes("5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21");
+
+        assert_eq!(tagged_hash("TapLeaf", &msg).to_vec(), want);
+    }
+
+    #[test]
+    fn tap_leaf_hash_commits_to_version_and_script() {
+        let script = hex_to_bytes("20d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8ac");
+        let want = hex_to_bytes("5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21");
+
+        assert_eq!(tap_leaf_hash(&script, 0xc0).to_vec(), want);
+    }
+}
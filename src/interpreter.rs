@@ -2,33 +2,405 @@
 
 //! Provides a type and logic to executed Bitcoin scripts.
 //!
-//! This is currently just a POC of the interpreter design. Does not
-//! have any transaction data so can only handle opcodes that do not
-//! require the transaction i.e., arithmetic.
+//! This is currently just a POC of the interpreter design. Opcodes
+//! that require the spending transaction can only be executed if a
+//! [`TxContext`] is provided.
 
-use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+use bitcoin::absolute::LOCK_TIME_THRESHOLD;
 use bitcoin::opcodes::all::*;
-use bitcoin::script::{ScriptBuf, ScriptExt, Instruction};
+use bitcoin::opcodes::Opcode;
+use bitcoin::script::{Instruction, Script, ScriptBuf, ScriptExt};
+use bitcoin::secp256k1::{ecdsa, Message, PublicKey, Secp256k1, XOnlyPublicKey};
+use bitcoin::sighash::{Annex, EcdsaSighashType, Prevouts, SighashCache};
+use bitcoin::taproot::{self, LeafVersion, TapLeafHash};
+use bitcoin::{consensus, transaction, Sequence, Witness};
+
+use crate::context::TxContext;
+use crate::error::ScriptError;
+use crate::flags::{VerificationFlag, VerificationFlags};
+use crate::hash::{self, DefaultBackend, HashBackend};
+use crate::stack::{is_truthy, Stack};
+
+/// The maximum number of items allowed on the stack.
+pub(crate) const MAX_STACK_SIZE: usize = 1000;
+
+/// The maximum size in bytes of an item pushed onto the stack.
+pub(crate) const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+
+/// The maximum number of non-push opcodes allowed in a script.
+const MAX_OPS_PER_SCRIPT: usize = 201;
+
+/// The maximum size in bytes of a script.
+pub(crate) const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// The maximum size in bytes of the lock time and sequence numbers used
+/// by `OP_CHECKLOCKTIMEVERIFY` and `OP_CHECKSEQUENCEVERIFY`.
+const LOCK_TIME_NUM_SIZE: usize = 5;
+
+/// If set in a sequence number relative lock time is disabled (BIP-68).
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
 
-use crate::stack::Stack;
+/// If set in a sequence number the relative lock time is in units of
+/// 512 seconds, otherwise it is in blocks (BIP-68).
+const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
 
-pub struct Interpreter {
+/// The bits of a sequence number holding the relative lock time (BIP-68).
+const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_ffff;
+
+/// The validation budget available to a tapscript in addition to the witness size.
+const VALIDATION_WEIGHT_OFFSET: i64 = 50;
+
+/// The validation budget used by each non-empty tapscript signature check.
+const VALIDATION_WEIGHT_PER_SIGOP_PASSED: i64 = 50;
+
+/// The maximum number of public keys allowed in a multisig.
+const MAX_PUBKEYS_PER_MULTISIG: i64 = 20;
+
+pub struct Interpreter<'a> {
     /// The stack used during script execution.
     stack: Stack,
+    /// The stack used by `OP_TOALTSTACK` and `OP_FROMALTSTACK`.
+    alt_stack: Stack,
     /// The script to interpret/execute.
     // TODO: This can be a `&Script` because we only need to call `instructions`.
     script: ScriptBuf,
+    /// The byte offset of the next instruction to execute.
+    pc: usize,
+    /// The position of the next instruction to execute.
+    index: usize,
+    /// Set while execution is paused part way through the script, by
+    /// [`Self::step`] or [`Self::restore_state`], so that the next
+    /// execution resumes instead of starting over.
+    paused: bool,
+    /// The byte offset in the script after the last executed `OP_CODESEPARATOR`.
+    code_separator: usize,
+    /// The opcode position of the last executed `OP_CODESEPARATOR`,
+    /// `u32::MAX` if none has been executed (used by tapscript).
+    code_separator_index: u32,
+    /// The rules the script is executed under.
+    sig_version: SigVersion,
+    /// The spending transaction, if one is available.
+    tx_context: Option<&'a TxContext>,
+    /// The verification flags to execute the script with.
+    flags: VerificationFlags,
+    /// The number of signature operations executed.
+    sigops: usize,
+    /// The number of non-push opcodes executed.
+    op_count: usize,
+    /// The maximum size in bytes of an item pushed onto the stack.
+    max_element_size: usize,
+    /// One entry per enclosing conditional, `true` if the branch executes.
+    condition_stack: Vec<bool>,
+    /// The byte offset of the `OP_IF` or `OP_NOTIF` opening each entry
+    /// of the condition stack.
+    open_conditionals: Vec<usize>,
+    /// The remaining tapscript validation budget, `None` if unlimited.
+    validation_budget: Option<i64>,
+    /// The validation budget set by [`Self::with_validation_budget`],
+    /// restored before each execution.
+    initial_validation_budget: Option<i64>,
+    /// The ECDSA signature hashes computed so far, keyed on the sighash
+    /// type and the position of the last executed `OP_CODESEPARATOR`,
+    /// along with the script code each was computed with.
+    sighash_cache: HashMap<(u32, usize), (ScriptBuf, Message)>,
+    /// The number of signature hashes computed, excluding cache hits.
+    sighashes_computed: usize,
+    /// The handlers for opcodes executed by a handler instead of the
+    /// interpreter, keyed on the opcode byte.
+    handlers: HashMap<u8, Box<dyn OpcodeHandler + 'a>>,
+}
+
+/// Executes an opcode in place of the interpreter, see
+/// [`Interpreter::with_opcode_handler`].
+///
+/// Useful to experiment with new opcodes, or changes to existing ones,
+/// without forking the crate. A handler only has access to the main
+/// stack, not the alt stack, the verification flags, or the transaction
+/// context.
+pub trait OpcodeHandler {
+    /// Executes `op` against `stack`.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if executing `op` triggers failure.
+    fn execute(&self, op: Opcode, stack: &mut Stack) -> Result<(), ScriptError>;
+}
+
+/// The signature version, the rules that a script is executed under.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SigVersion {
+    /// Legacy scripts i.e., the script sig, script pubkey, and P2SH redeem scripts.
+    Base,
+    /// Segwit v0 witness scripts.
+    WitnessV0,
+    /// Taproot script path spends (BIP-342).
+    Tapscript,
+}
+
+/// The outcome of executing a script, see [`Interpreter::execute_script_with_result`].
+pub struct ExecutionResult {
+    /// Each successfully executed instruction.
+    pub trace: Vec<StepInfo>,
+    /// The stack after execution, top item last.
+    pub stack: Vec<Vec<u8>>,
+    /// `Ok(top_of_stack)` if script terminated successfully, otherwise
+    /// the error that triggered failure.
+    pub result: Result<bool, ScriptError>,
+    /// The byte offset and opcode of the instruction that triggered
+    /// failure, or of the unmatched `OP_IF` if the script ends inside a
+    /// conditional.
+    pub failed_at: Option<(usize, Opcode)>,
+}
+
+impl ExecutionResult {
+    /// Renders a human readable report of the execution.
+    ///
+    /// One line per executed instruction with the stack after it,
+    /// followed by the final stack and either the result or the error
+    /// and the instruction that triggered it.
+    pub fn render_report(&self) -> String {
+        let render_stack = |stack: &[Vec<u8>]| stack.iter().map(|item| crate::to_hex(item)).collect::<Vec<_>>().join(" ");
+
+        let mut lines = self
+            .trace
+            .iter()
+            .map(|step| format!("{}: {} [{}]", step.pc, step.opcode, render_stack(&step.stack)))
+            .collect::<Vec<_>>();
+        lines.push(format!("final stack: [{}]", render_stack(&self.stack)));
+        match (&self.result, self.failed_at) {
+            (Ok(res), _) => lines.push(format!("result: {}", res)),
+            (Err(e), Some((pc, opcode))) => lines.push(format!("error at {} ({}): {}", pc, opcode, e)),
+            (Err(e), None) => lines.push(format!("error: {}", e)),
+        }
+        lines.join("\n")
+    }
+}
+
+/// A single executed instruction, see [`Interpreter::step`] and
+/// [`Interpreter::execute_script_with_trace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepInfo {
+    /// The byte offset of the executed instruction within the script.
+    pub pc: usize,
+    /// The executed opcode, for pushes this is the push opcode.
+    pub opcode: Opcode,
+    /// The stack after executing the instruction, top item last.
+    pub stack: Vec<Vec<u8>>,
+    /// The condition stack after executing the instruction, see
+    /// [`Interpreter::condition_stack`].
+    pub condition_stack: Vec<bool>,
+}
+
+/// An iterator over the steps of script execution, see [`Interpreter::steps`].
+pub struct Steps<'i, 'a> {
+    /// The interpreter executing the script.
+    interpreter: &'i mut Interpreter<'a>,
+    /// Set once the script has completed or failed.
+    done: bool,
+}
+
+impl Iterator for Steps<'_, '_> {
+    type Item = Result<StepInfo, ScriptError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.interpreter.step() {
+            Ok(Some(step)) => Some(Ok(step)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// A snapshot of an interpreter part way through execution, see
+/// [`Interpreter::save_state`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedState {
+    /// The byte offset of the next instruction to execute.
+    pc: usize,
+    /// The position of the next instruction to execute.
+    index: usize,
+    /// The main stack, top item last.
+    stack: Vec<Vec<u8>>,
+    /// The alt stack, top item last.
+    alt_stack: Vec<Vec<u8>>,
+    /// The condition stack.
+    condition_stack: Vec<bool>,
+    /// The byte offset of the opcode opening each conditional.
+    open_conditionals: Vec<usize>,
+    /// The number of non-push opcodes executed.
+    op_count: usize,
+    /// The number of signature operations executed.
+    sigops: usize,
+    /// The remaining tapscript validation budget.
+    validation_budget: Option<i64>,
+    /// The byte offset after the last executed `OP_CODESEPARATOR`.
+    code_separator: usize,
+    /// The opcode position of the last executed `OP_CODESEPARATOR`.
+    code_separator_index: u32,
 }
 
-impl Interpreter {
+impl<'a> Interpreter<'a> {
     /// Creates a new script interpreter.
     pub fn new(script: ScriptBuf) -> Self {
         Self {
             stack: Stack::new(),
+            alt_stack: Stack::new(),
             script,
+            pc: 0,
+            index: 0,
+            paused: false,
+            code_separator: 0,
+            code_separator_index: u32::MAX,
+            sig_version: SigVersion::Base,
+            tx_context: None,
+            flags: VerificationFlags::CONSENSUS,
+            sigops: 0,
+            op_count: 0,
+            max_element_size: MAX_SCRIPT_ELEMENT_SIZE,
+            condition_stack: Vec::new(),
+            open_conditionals: Vec::new(),
+            validation_budget: None,
+            initial_validation_budget: None,
+            sighash_cache: HashMap::new(),
+            sighashes_computed: 0,
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Sets the verification flags, defaults to [`VerificationFlags::CONSENSUS`].
+    pub fn with_flags(mut self, flags: VerificationFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Sets the maximum size in bytes of an item pushed onto the stack,
+    /// defaults to the consensus limit of 520 bytes.
+    ///
+    /// Useful to experiment with proposals that change the limit.
+    pub fn with_max_element_size(mut self, max_element_size: usize) -> Self {
+        self.max_element_size = max_element_size;
+        self
+    }
+
+    /// Sets the tapscript validation budget (BIP-342), defaults to the
+    /// budget for the witness of the input being validated if there is a
+    /// transaction context, otherwise unlimited.
+    ///
+    /// Each non-empty signature checked uses 50 of the budget and
+    /// execution fails if the budget goes negative.
+    pub fn with_validation_budget(mut self, budget: i64) -> Self {
+        self.validation_budget = Some(budget);
+        self.initial_validation_budget = Some(budget);
+        self
+    }
+
+    /// Registers `handler` to execute `op`, replacing the built in
+    /// implementation if there is one.
+    ///
+    /// The handler is used even if `op` is disabled, the opcode count,
+    /// stack size, and element size limits still apply.
+    pub fn with_opcode_handler(mut self, op: Opcode, handler: impl OpcodeHandler + 'a) -> Self {
+        self.handlers.insert(op.to_u8(), Box::new(handler));
+        self
+    }
+
+    /// Sets the signature version, defaults to [`SigVersion::Base`].
+    pub fn with_sig_version(mut self, sig_version: SigVersion) -> Self {
+        self.sig_version = sig_version;
+        self
+    }
+
+    /// Sets the stack that execution starts with.
+    pub(crate) fn with_stack(mut self, stack: Stack) -> Self {
+        self.stack = stack;
+        self
+    }
+
+    /// Returns the number of signature operations executed so far.
+    ///
+    /// Signature checks in tapscript are limited by the validation weight
+    /// budget instead so are not counted.
+    pub fn sigops(&self) -> usize {
+        self.sigops
+    }
+
+    /// Returns the number of ECDSA signature hashes computed so far.
+    ///
+    /// Signature checks with the same sighash type, after the same
+    /// `OP_CODESEPARATOR`, share a single signature hash.
+    pub fn sighashes_computed(&self) -> usize {
+        self.sighashes_computed
+    }
+
+    /// Returns the condition stack, one entry per enclosing `OP_IF` or
+    /// `OP_NOTIF` that is `true` if the branch executes.
+    pub fn condition_stack(&self) -> &[bool] {
+        &self.condition_stack
+    }
+
+    /// Saves the execution state so execution can be resumed later.
+    ///
+    /// Execution is paused between instructions, for example by only
+    /// taking some of the [`Self::steps`]. The script, flags, and
+    /// transaction context are not saved.
+    pub fn save_state(&self) -> SavedState {
+        SavedState {
+            pc: self.pc,
+            index: self.index,
+            stack: self.stack.items().to_vec(),
+            alt_stack: self.alt_stack.items().to_vec(),
+            condition_stack: self.condition_stack.clone(),
+            open_conditionals: self.open_conditionals.clone(),
+            op_count: self.op_count,
+            sigops: self.sigops,
+            validation_budget: self.validation_budget,
+            code_separator: self.code_separator,
+            code_separator_index: self.code_separator_index,
         }
     }
 
+    /// Restores execution state saved by [`Self::save_state`], execution
+    /// then continues from the next instruction after the pause.
+    ///
+    /// The interpreter must have been created with the same script, flags,
+    /// and transaction context as the one the state was saved from.
+    pub fn restore_state(&mut self, state: SavedState) {
+        self.paused = true;
+        self.pc = state.pc;
+        self.index = state.index;
+        self.stack = Stack::from(state.stack);
+        self.alt_stack = Stack::from(state.alt_stack);
+        self.condition_stack = state.condition_stack;
+        self.open_conditionals = state.open_conditionals;
+        self.op_count = state.op_count;
+        self.sigops = state.sigops;
+        self.validation_budget = state.validation_budget;
+        self.code_separator = state.code_separator;
+        self.code_separator_index = state.code_separator_index;
+    }
+
+    /// Consumes the interpreter returning the stack.
+    pub(crate) fn into_stack(self) -> Stack {
+        self.stack
+    }
+
+    /// Sets the spending transaction used by opcodes that inspect it.
+    pub fn with_tx_context(mut self, tx_context: &'a TxContext) -> Self {
+        self.tx_context = Some(tx_context);
+        self
+    }
+
     /// Checks if the script is valid.
     ///
     /// > A transaction is valid if nothing in the combined script
@@ -43,109 +415,2592 @@ impl Interpreter {
 
     /// Executes the current script.
     ///
+    /// Execution starts from the beginning of the script, with the current
+    /// stack, unless it is paused by [`Self::step`] or [`Self::restore_state`]
+    /// in which case it resumes from the next instruction.
+    ///
     /// # Returns
     ///
     /// - `Ok(top_of_stack)` if script terminated successfully.
     /// - `Err()` if something in the script triggered failure.
-    pub fn execute_script(&mut self) -> Result<bool> {
-        for ins in self.script.clone().instructions() {
-            match ins? {
-                Instruction::PushBytes(ref p) => self.stack.push(p.as_bytes()),
-                Instruction::Op(ref op) => {
-                    match *op {
-                        OP_RETURN => {
-                            // OP_RETURN causes script to immediately fail.
-                            bail!("OP_RETURN");
-                        },
-                        // Constants
-                        OP_PUSHNUM_NEG1 => self.stack.push_num(-1),
-                        OP_PUSHBYTES_0 => self.stack.push(&[]),
-                        OP_PUSHNUM_1 => self.stack.push_num(1),
-                        OP_PUSHNUM_2 => self.stack.push_num(2),
-                        OP_PUSHNUM_3 => self.stack.push_num(3),
-                        OP_PUSHNUM_4 => self.stack.push_num(4),
-                        OP_PUSHNUM_5 => self.stack.push_num(5),
-                        OP_PUSHNUM_6 => self.stack.push_num(6),
-                        OP_PUSHNUM_7 => self.stack.push_num(7),
-                        OP_PUSHNUM_8 => self.stack.push_num(8),
-                        OP_PUSHNUM_9 => self.stack.push_num(9),
-                        OP_PUSHNUM_10 => self.stack.push_num(10),
-                        OP_PUSHNUM_11 => self.stack.push_num(11),
-                        OP_PUSHNUM_12 => self.stack.push_num(12),
-                        OP_PUSHNUM_13 => self.stack.push_num(13),
-                        OP_PUSHNUM_14 => self.stack.push_num(14),
-                        OP_PUSHNUM_15 => self.stack.push_num(15),
-                        OP_PUSHNUM_16 => self.stack.push_num(16),
-                        // Arithmetic
-                        OP_ADD => self.add()?,
-                        OP_SUB => todo!(),
-                        // Bitwise logic
-                        OP_EQUAL => self.equal()?,
-                        other => panic!("{}", format!("opcode not yet supported: {}", other)),
+    pub fn execute_script(&mut self) -> Result<bool, ScriptError> {
+        self.execute_script_with_trace(|_| {})
+    }
+
+    /// Executes the current script from the beginning starting with
+    /// `stack`, replacing the current stack.
+    ///
+    /// Used to execute the script pubkey against the stack left by the
+    /// script sig.
+    ///
+    /// # Returns
+    ///
+    /// Same as [`Self::execute_script`].
+    pub fn execute_script_with_stack(&mut self, stack: Stack) -> Result<bool, ScriptError> {
+        self.paused = false;
+        self.stack = stack;
+        self.execute_script()
+    }
+
+    /// Executes the current script, calling `on_step` after each
+    /// instruction is successfully executed.
+    ///
+    /// # Returns
+    ///
+    /// Same as [`Self::execute_script`].
+    pub fn execute_script_with_trace<F>(&mut self, mut on_step: F) -> Result<bool, ScriptError>
+    where
+        F: FnMut(&StepInfo),
+    {
+        if !self.paused {
+            self.reset();
+        }
+        // Once this returns, by completing or failing, execution is no
+        // longer paused.
+        self.paused = false;
+        self.check_script_size()?;
+        self.init_validation_budget();
+
+        let script = self.script.clone();
+        let start = self.pc;
+        for (offset, ins) in Script::from_bytes(&script.as_bytes()[start..]).instruction_indices() {
+            let pc = start + offset;
+            // A truncated push fails to parse.
+            self.execute_next(pc, ins.map_err(|_| ScriptError::BadOpcode)?)?;
+            on_step(&self.step_info(pc));
+        }
+        if !self.condition_stack.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
+        }
+        Ok(self.stack.is_true())
+    }
+
+    /// Executes the current script, collecting the trace, the final
+    /// stack, and the position of any failure.
+    pub fn execute_script_with_result(&mut self) -> ExecutionResult {
+        let mut trace = Vec::new();
+        let result = self.execute_script_with_trace(|step| trace.push(step.clone()));
+
+        // Execution stops at the instruction that triggered failure, if
+        // the script ends inside a conditional this is the unmatched `OP_IF`.
+        let failed_at = match result {
+            Ok(_) => None,
+            Err(_) if self.pc == self.script.len() => self.open_conditionals.last().copied(),
+            Err(_) => Some(self.pc),
+        };
+        let failed_at = failed_at.and_then(|pc| self.script.as_bytes().get(pc).map(|op| (pc, Opcode::from(*op))));
+        ExecutionResult { trace, stack: self.stack.items().to_vec(), result, failed_at }
+    }
+
+    /// Returns an iterator that lazily executes the current script one
+    /// instruction at a time.
+    ///
+    /// Each step yields the executed instruction and a snapshot of the
+    /// stacks after executing it. Iteration stops after the last instruction or
+    /// after yielding the first error.
+    pub fn steps(&mut self) -> Steps<'_, 'a> {
+        Steps { interpreter: self, done: false }
+    }
+
+    /// Executes the next instruction of the current script.
+    ///
+    /// Unless execution is paused, by an earlier step or by
+    /// [`Self::restore_state`], this starts again from the beginning of
+    /// the script.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some(step))` the executed instruction and the stack after it.
+    /// - `Ok(None)` if the script has completed.
+    /// - `Err()` if the instruction, or reaching the end of the script
+    ///   inside a conditional, triggered failure.
+    pub fn step(&mut self) -> Result<Option<StepInfo>, ScriptError> {
+        if !self.paused {
+            self.reset();
+        }
+        // Execution stays paused until the script completes or fails.
+        self.paused = false;
+        self.check_script_size()?;
+        self.init_validation_budget();
+
+        let pc = self.pc;
+        let remaining = &self.script.as_bytes()[pc..];
+        if remaining.is_empty() {
+            if !self.condition_stack.is_empty() {
+                return Err(ScriptError::UnbalancedConditional);
+            }
+            return Ok(None);
+        }
+        let opcode = Opcode::from(remaining[0]);
+        // Copy the instruction so that it does not borrow the script, a
+        // truncated push is copied as is and fails to parse.
+        let len = instruction_len(remaining).unwrap_or(remaining.len());
+        let instruction = ScriptBuf::from_bytes(remaining[..len].to_vec());
+        let ins = instruction.instructions().next().expect("instruction is not empty").map_err(|_| ScriptError::BadOpcode)?;
+
+        self.execute_next(pc, ins)?;
+        self.paused = true;
+        Ok(Some(self.step_info(pc)))
+    }
+
+    /// Executes the rest of the current script one instruction at a time,
+    /// see [`Self::step`].
+    ///
+    /// # Returns
+    ///
+    /// Every executed instruction, or the error that triggered failure.
+    pub fn trace(&mut self) -> Result<Vec<StepInfo>, ScriptError> {
+        let mut steps = Vec::new();
+        while let Some(step) = self.step()? {
+            steps.push(step);
+        }
+        Ok(steps)
+    }
+
+    /// Returns the step for the instruction at byte offset `pc`, just executed.
+    fn step_info(&self, pc: usize) -> StepInfo {
+        StepInfo {
+            pc,
+            opcode: Opcode::from(self.script.as_bytes()[pc]),
+            stack: self.stack.items().to_vec(),
+            condition_stack: self.condition_stack.clone(),
+        }
+    }
+
+    /// Fails if the script is too big, tapscript has no script size limit (BIP-342).
+    fn check_script_size(&self) -> Result<(), ScriptError> {
+        if self.sig_version != SigVersion::Tapscript && self.script.len() > MAX_SCRIPT_SIZE {
+            return Err(ScriptError::ScriptSize);
+        }
+        Ok(())
+    }
+
+    /// Moves back to the start of the script, clearing the state left by
+    /// any earlier execution except the stack.
+    fn reset(&mut self) {
+        self.pc = 0;
+        self.index = 0;
+        self.alt_stack = Stack::new();
+        self.condition_stack.clear();
+        self.open_conditionals.clear();
+        self.op_count = 0;
+        self.sigops = 0;
+        self.validation_budget = self.initial_validation_budget;
+        self.sighash_cache.clear();
+        self.sighashes_computed = 0;
+        self.code_separator = 0;
+        self.code_separator_index = u32::MAX;
+    }
+
+    /// Sets the tapscript validation budget from the witness of the input
+    /// being validated, unless it is already set.
+    fn init_validation_budget(&mut self) {
+        if self.sig_version == SigVersion::Tapscript && self.validation_budget.is_none() {
+            self.validation_budget = self.tx_context.map(|ctx| validation_budget(&ctx.input().witness));
+        }
+    }
+
+    /// Executes the next instruction `ins`, at byte offset `pc`, and
+    /// advances to the instruction after it.
+    fn execute_next(&mut self, pc: usize, ins: Instruction) -> Result<(), ScriptError> {
+        self.execute_instruction(self.index, pc, ins)?;
+        // The instruction parsed so its length is known to be valid.
+        self.pc = pc + instruction_len(&self.script.as_bytes()[pc..]).expect("valid instruction");
+        self.index += 1;
+        Ok(())
+    }
+
+    /// Executes a single instruction, `index` is the position of the
+    /// instruction within the script and `pc` its byte offset.
+    fn execute_instruction(&mut self, index: usize, pc: usize, ins: Instruction) -> Result<(), ScriptError> {
+        let executing = !self.condition_stack.contains(&false);
+        match ins {
+            Instruction::PushBytes(ref p) => {
+                if p.len() > self.max_element_size {
+                    return Err(ScriptError::PushSize);
+                }
+                if executing {
+                    if self.flags.contains(VerificationFlag::MinimalData)
+                        && !is_minimal_push(self.script.as_bytes()[pc], p.as_bytes())
+                    {
+                        return Err(ScriptError::MinimalData);
+                    }
+                    self.stack.push(p.as_bytes())
+                }
+            },
+            Instruction::Op(ref op) => {
+                // Tapscript has no opcode limit, signatures are limited by
+                // the validation weight budget instead.
+                if op.to_u8() > OP_PUSHNUM_16.to_u8() && self.sig_version != SigVersion::Tapscript {
+                    self.op_count += 1;
+                    if self.op_count > MAX_OPS_PER_SCRIPT {
+                        return Err(ScriptError::OpCount);
                     }
                 }
+                if is_disabled(*op) && !self.handlers.contains_key(&op.to_u8()) {
+                    return Err(ScriptError::DisabledOpcode);
+                }
+
+                // Conditionals are executed even in a branch that is not
+                // executed to keep track of the nesting.
+                let conditional = (OP_IF.to_u8()..=OP_ENDIF.to_u8()).contains(&op.to_u8());
+                let depth = self.stack.len();
+                let res = match self.handlers.get(&op.to_u8()) {
+                    Some(handler) if executing => {
+                        handler.execute(*op, &mut self.stack).and_then(|()| self.check_element_sizes())
+                    }
+                    None if executing || conditional => self.execute_opcode(index, pc, *op),
+                    _ => Ok(()),
+                };
+                res.map_err(|e| match e {
+                    // With an item on the stack `OP_PICK` and `OP_ROLL` can
+                    // only fail on a negative or out of range index.
+                    ScriptError::InvalidStackOperation if matches!(*op, OP_PICK | OP_ROLL) && depth > 0 => e,
+                    ScriptError::InvalidStackOperation => ScriptError::StackUnderflow { opcode: *op },
+                    e => e,
+                })?;
             }
         }
-        Ok(self.stack.is_true())
+        if self.stack.len() + self.alt_stack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
+        Ok(())
+    }
+
+    /// Fails if an item on the stack exceeds the maximum element size.
+    ///
+    /// Used after executing an opcode handler, which can push any item.
+    fn check_element_sizes(&self) -> Result<(), ScriptError> {
+        if self.stack.items().iter().any(|item| item.len() > self.max_element_size) {
+            return Err(ScriptError::PushSize);
+        }
+        Ok(())
+    }
+
+    /// Executes the non-push opcode `op`, `index` is the position of the
+    /// instruction within the script and `pc` its byte offset.
+    fn execute_opcode(&mut self, index: usize, pc: usize, op: Opcode) -> Result<(), ScriptError> {
+        match op {
+            OP_RETURN => {
+                // OP_RETURN causes script to immediately fail.
+                return Err(ScriptError::EarlyReturn);
+            },
+            // Flow control
+            OP_IF | OP_NOTIF => {
+                self.begin_conditional(op)?;
+                self.open_conditionals.push(pc);
+            },
+            OP_ELSE => match self.condition_stack.last_mut() {
+                Some(executing) => *executing = !*executing,
+                None => return Err(ScriptError::UnbalancedConditional),
+            },
+            OP_ENDIF => {
+                if self.condition_stack.pop().is_none() {
+                    return Err(ScriptError::UnbalancedConditional);
+                }
+                self.open_conditionals.pop();
+            },
+            // Invalid even when not executed.
+            OP_VERIF | OP_VERNOTIF => return Err(ScriptError::BadOpcode),
+            // Constants
+            OP_PUSHNUM_NEG1 => self.stack.push_num(-1),
+            OP_PUSHBYTES_0 => self.stack.push(&[]),
+            OP_PUSHNUM_1 => self.stack.push_num(1),
+            OP_PUSHNUM_2 => self.stack.push_num(2),
+            OP_PUSHNUM_3 => self.stack.push_num(3),
+            OP_PUSHNUM_4 => self.stack.push_num(4),
+            OP_PUSHNUM_5 => self.stack.push_num(5),
+            OP_PUSHNUM_6 => self.stack.push_num(6),
+            OP_PUSHNUM_7 => self.stack.push_num(7),
+            OP_PUSHNUM_8 => self.stack.push_num(8),
+            OP_PUSHNUM_9 => self.stack.push_num(9),
+            OP_PUSHNUM_10 => self.stack.push_num(10),
+            OP_PUSHNUM_11 => self.stack.push_num(11),
+            OP_PUSHNUM_12 => self.stack.push_num(12),
+            OP_PUSHNUM_13 => self.stack.push_num(13),
+            OP_PUSHNUM_14 => self.stack.push_num(14),
+            OP_PUSHNUM_15 => self.stack.push_num(15),
+            OP_PUSHNUM_16 => self.stack.push_num(16),
+            // Stack
+            OP_TOALTSTACK => {
+                let item = self.stack.pop_bytes()?;
+                self.alt_stack.push(&item)
+            },
+            OP_FROMALTSTACK => match self.alt_stack.pop() {
+                Some(item) => self.stack.push(&item),
+                None => return Err(ScriptError::InvalidAltstackOperation),
+            },
+            OP_2DROP => self.stack.drop2()?,
+            OP_IFDUP => self.stack.if_dup()?,
+            OP_DROP => self.stack.drop()?,
+            OP_DUP => self.stack.dup()?,
+            OP_NIP => self.stack.nip()?,
+            OP_2DUP => self.stack.dup2()?,
+            OP_3DUP => self.stack.dup3()?,
+            OP_OVER => self.stack.over()?,
+            OP_PICK => self.pick()?,
+            OP_ROLL => self.roll()?,
+            OP_ROT => self.stack.rot()?,
+            OP_SWAP => self.stack.swap()?,
+            OP_TUCK => self.stack.tuck()?,
+            OP_VERIFY => self.verify(ScriptError::Verify)?,
+            // Arithmetic
+            OP_ADD => self.add()?,
+            OP_SUB => self.sub()?,
+            OP_NUMEQUAL => self.num_equal()?,
+            OP_NUMNOTEQUAL => self.compare_nums(|a, b| a != b)?,
+            OP_LESSTHAN => self.compare_nums(|a, b| a < b)?,
+            OP_GREATERTHAN => self.compare_nums(|a, b| a > b)?,
+            OP_LESSTHANOREQUAL => self.compare_nums(|a, b| a <= b)?,
+            OP_GREATERTHANOREQUAL => self.compare_nums(|a, b| a >= b)?,
+            // Bitwise logic
+            OP_EQUAL => self.bytes_equal()?,
+            OP_EQUALVERIFY => {
+                self.bytes_equal()?;
+                self.verify(ScriptError::EqualVerify)?
+            },
+            // Crypto
+            OP_RIPEMD160 => self.hash(DefaultBackend::ripemd160)?,
+            OP_SHA1 => self.hash(DefaultBackend::sha1)?,
+            OP_SHA256 => self.hash(DefaultBackend::sha256)?,
+            OP_HASH160 => self.hash(DefaultBackend::hash160)?,
+            OP_HASH256 => self.hash(DefaultBackend::hash256)?,
+            OP_CODESEPARATOR => {
+                self.code_separator = pc + 1;
+                self.code_separator_index = index as u32;
+            },
+            OP_CHECKSIG => {
+                let res = self.check_sig()?;
+                self.stack.push_bool(res)
+            },
+            OP_CHECKSIGVERIFY => {
+                if !self.check_sig()? {
+                    return Err(ScriptError::CheckSigVerify);
+                }
+            },
+            OP_CHECKSIGADD => self.check_sig_add()?,
+            OP_CHECKMULTISIG => {
+                let res = self.check_multisig()?;
+                self.stack.push_bool(res)
+            },
+            OP_CHECKMULTISIGVERIFY => {
+                if !self.check_multisig()? {
+                    return Err(ScriptError::CheckMultisigVerify);
+                }
+            },
+            // Locktime
+            OP_CLTV => {
+                // Without the flag OP_CHECKLOCKTIMEVERIFY is OP_NOP2.
+                if self.flags.contains(VerificationFlag::CheckLockTimeVerify) {
+                    self.check_lock_time_verify()?
+                }
+            },
+            OP_CSV => {
+                // Without the flag OP_CHECKSEQUENCEVERIFY is OP_NOP3.
+                if self.flags.contains(VerificationFlag::CheckSequenceVerify) {
+                    self.check_sequence_verify()?
+                }
+            },
+            other => return Err(ScriptError::UnsupportedOpcode(other)),
+        }
+        Ok(())
+    }
+
+    /// Starts a conditional branch for `OP_IF` or `OP_NOTIF`.
+    ///
+    /// If the current branch is executing the top stack item is removed
+    /// and decides if the new branch executes.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the current branch is executing and the stack
+    /// is empty, or if the argument is not minimal when `MINIMALIF`
+    /// applies.
+    fn begin_conditional(&mut self, op: Opcode) -> Result<(), ScriptError> {
+        if self.condition_stack.contains(&false) {
+            self.condition_stack.push(false);
+            return Ok(());
+        }
+
+        let item = self.stack.pop_bytes()?;
+        // Minimal if is consensus in tapscript and policy in segwit v0.
+        let minimal_if = match self.sig_version {
+            SigVersion::Base => false,
+            SigVersion::WitnessV0 => self.flags.contains(VerificationFlag::MinimalIf),
+            SigVersion::Tapscript => true,
+        };
+        if minimal_if && !(item.is_empty() || item == [0x01]) {
+            return Err(ScriptError::MinimalIf);
+        }
+        let value = is_truthy(&item);
+        self.condition_stack.push(if op == OP_NOTIF { !value } else { value });
+        Ok(())
+    }
+
+    /// Removes the top stack item `n` and copies the item `n` places
+    /// down the stack to the top (`OP_PICK`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if `n` is negative or there are not more than `n`
+    /// items left on the stack.
+    fn pick(&mut self) -> Result<(), ScriptError> {
+        let n = self.pop_stack_index()?;
+        self.stack.pick(n)
+    }
+
+    /// Removes the top stack item `n` and moves the item `n` places
+    /// down the stack to the top (`OP_ROLL`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if `n` is negative or there are not more than `n`
+    /// items left on the stack.
+    fn roll(&mut self) -> Result<(), ScriptError> {
+        let n = self.pop_stack_index()?;
+        self.stack.roll(n)
+    }
+
+    /// Removes the top stack item and returns it as an index into the
+    /// stack, `0` being the top item.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the stack is empty or the index is negative.
+    fn pop_stack_index(&mut self) -> Result<usize, ScriptError> {
+        let n = self.pop_num()?;
+        if n < 0 {
+            return Err(ScriptError::InvalidStackOperation);
+        }
+        Ok(n as usize)
+    }
+
+    /// Removes the top stack item and returns it as a number.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the stack is empty, the top item is not a
+    /// valid scriptint, or if it is not minimally encoded when
+    /// `MINIMALDATA` is set.
+    fn pop_num(&mut self) -> Result<i64, ScriptError> {
+        self.check_minimal_num()?;
+        self.stack.pop_num()
+    }
+
+    /// Fails if `MINIMALDATA` is set and the top stack item is not a
+    /// minimally encoded number.
+    fn check_minimal_num(&self) -> Result<(), ScriptError> {
+        if !self.flags.contains(VerificationFlag::MinimalData) {
+            return Ok(());
+        }
+        match self.stack.top() {
+            Some(top) if !is_minimal_num(top) => return Err(ScriptError::InvalidScriptInt),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the top stack item and fails with `err` if it is false.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the stack is empty or the top item is false.
+    fn verify(&mut self, err: ScriptError) -> Result<(), ScriptError> {
+        let item = self.stack.pop_bytes()?;
+        if !is_truthy(&item) {
+            return Err(err);
+        }
+        Ok(())
     }
 
     /// Removes the top two stack items, adds them together, and
     /// pushes the result back onto the stack.
     ///
+    /// The operands are at most 4 bytes but the result can be 5 bytes.
+    ///
     /// # Returns
     ///
     /// Returns an error if there are not two numbers on the stack.
-    fn add(&mut self) -> Result<()> {
-        let a = self.stack.pop_num()?;
-        let b = self.stack.pop_num()?;
+    fn add(&mut self) -> Result<(), ScriptError> {
+        let a = self.pop_num()?;
+        let b = self.pop_num()?;
         let res = a + b;
-        Ok(self.stack.push_num(res))
+        self.stack.push_num(res);
+        Ok(())
+    }
+
+    /// Removes the top two stack items, subtracts the top item from the
+    /// second to top item, and pushes the result back onto the stack.
+    ///
+    /// The operands are at most 4 bytes but the result can be 5 bytes.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are not two numbers on the stack.
+    fn sub(&mut self) -> Result<(), ScriptError> {
+        let b = self.pop_num()?;
+        let a = self.pop_num()?;
+        let res = a - b;
+        self.stack.push_num(res);
+        Ok(())
     }
 
-    fn equal(&mut self) -> Result<()> {
-        let a = self.stack.pop_num()?;
-        let b = self.stack.pop_num()?;
+    /// Removes the top two stack items and pushes `true` if they are
+    /// byte for byte equal, `false` otherwise (`OP_EQUAL`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are not two items on the stack.
+    fn bytes_equal(&mut self) -> Result<(), ScriptError> {
+        let a = self.stack.pop_bytes()?;
+        let b = self.stack.pop_bytes()?;
         let res = a == b;
-        Ok(self.stack.push_bool(res))
+        self.stack.push_bool(res);
+        Ok(())
+    }
 
+    /// Removes the top two stack items and pushes `true` if they are
+    /// numerically equal, `false` otherwise (`OP_NUMEQUAL`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are not two numbers on the stack.
+    fn num_equal(&mut self) -> Result<(), ScriptError> {
+        let a = self.pop_num()?;
+        let b = self.pop_num()?;
+        let res = a == b;
+        self.stack.push_bool(res);
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use bitcoin::script::ScriptExt;
-    use bitcoin::Script;
+    /// Removes the top two stack items and pushes the result of `cmp`,
+    /// called with the second to top item then the top item (e.g.
+    /// `OP_LESSTHAN`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are not two numbers on the stack.
+    fn compare_nums(&mut self, cmp: fn(i64, i64) -> bool) -> Result<(), ScriptError> {
+        let b = self.pop_num()?;
+        let a = self.pop_num()?;
+        let res = cmp(a, b);
+        self.stack.push_bool(res);
+        Ok(())
+    }
 
-    #[test]
-    fn op_return_false_no_data() {
-        let script = Script::builder().push_opcode(OP_RETURN).into_script();
-        assert!(Interpreter::new(script.clone()).execute_script().is_err());
-        assert!(!Interpreter::new(script).script_is_valid());
+    /// Removes the top stack item and pushes the digest produced by `hash_fn`.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the stack is empty.
+    fn hash<const N: usize>(&mut self, hash_fn: fn(&[u8]) -> [u8; N]) -> Result<(), ScriptError> {
+        let item = self.stack.pop_bytes()?;
+        self.stack.push(&hash_fn(&item));
+        Ok(())
     }
 
-    #[test]
-    fn op_return_false_with_data() {
-        let script = Script::builder()
-            .push_opcode(OP_RETURN)
-            .push_slice(&[0xab, 32]) // Just push some nonsense.
-            .into_script();
+    /// Removes the arguments to `OP_CHECKMULTISIG` from the stack and
+    /// checks the signatures against the public keys.
+    ///
+    /// The signatures must be in the same order as the public keys they
+    /// are checked against.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if all signatures are valid. Returns an error if
+    /// the stack does not hold the arguments, if a count is out of range,
+    /// or if the dummy element is not empty when `NULLDUMMY` is set.
+    fn check_multisig(&mut self) -> Result<bool, ScriptError> {
+        if self.sig_version == SigVersion::Tapscript {
+            return Err(ScriptError::TapscriptCheckMultisig);
+        }
 
-        assert!(Interpreter::new(script.clone()).execute_script().is_err());
-        assert!(!Interpreter::new(script).script_is_valid());
-    }
+        let n = self.pop_num()?;
+        if !(0..=MAX_PUBKEYS_PER_MULTISIG).contains(&n) {
+            return Err(ScriptError::PubkeyCount);
+        }
+        self.sigops += n as usize;
+        // Each public key counts toward the opcode limit.
+        self.op_count += n as usize;
+        if self.op_count > MAX_OPS_PER_SCRIPT {
+            return Err(ScriptError::OpCount);
+        }
+        let pubkeys = (0..n).map(|_| self.stack.pop_bytes()).collect::<Result<Vec<_>, _>>()?;
 
-    #[test]
-    fn add() {
-        let script = Script::builder()
-            .push_int(5).unwrap()
-            .push_int(2).unwrap()
-            .push_int(3).unwrap()
-            .push_opcode(OP_ADD)
-            .push_opcode(OP_EQUAL)
-            .into_script();
+        let m = self.pop_num()?;
+        if !(0..=n).contains(&m) {
+            return Err(ScriptError::SigCount);
+        }
+        let sigs = (0..m).map(|_| self.stack.pop_bytes()).collect::<Result<Vec<_>, _>>()?;
 
-        assert!(Interpreter::new(script).script_is_valid());
-    }
-}
+        // The signatures can not sign themselves, segwit signatures do
+        // not sign the script code so are not removed.
+        let script_code = if self.sig_version == SigVersion::Base {
+            sigs.iter().fold(self.script_code(), |script_code, sig| find_and_delete(&script_code, sig))
+        } else {
+            self.script_code()
+        };
 
+        let (mut isig, mut ikey) = (0, 0);
+        let mut success = true;
+        while success && isig < sigs.len() {
+            if self.check_ecdsa_signature(&sigs[isig], &pubkeys[ikey], &script_code)? {
+                isig += 1;
+            }
+            ikey += 1;
+            // If there are more signatures left than keys left too many
+            // signatures have failed.
+            if sigs.len() - isig > pubkeys.len() - ikey {
+                success = false;
+            }
+        }
+
+        // A bug in the original implementation consumes one extra element.
+        let dummy = self.stack.pop_bytes()?;
+        if self.flags.contains(VerificationFlag::NullDummy) && !dummy.is_empty() {
+            return Err(ScriptError::SigNullDummy);
+        }
+        Ok(success)
+    }
+
+    /// Removes the signature and public key arguments to `OP_CHECKSIG`
+    /// from the stack and checks the signature.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if the signature is valid. Returns an error if the
+    /// stack does not hold the arguments.
+    fn check_sig(&mut self) -> Result<bool, ScriptError> {
+        let pubkey = self.stack.pop_bytes()?;
+        let sig = self.stack.pop_bytes()?;
+        if self.sig_version == SigVersion::Tapscript {
+            return self.check_tapscript_signature(&sig, &pubkey);
+        }
+        self.sigops += 1;
+
+        // The signature can not sign itself, segwit signatures do not
+        // sign the script code so it is not removed.
+        let script_code = if self.sig_version == SigVersion::Base {
+            find_and_delete(&self.script_code(), &sig)
+        } else {
+            self.script_code()
+        };
+        self.check_ecdsa_signature(&sig, &pubkey, &script_code)
+    }
+
+    /// Returns `true` if `sig` is a valid signature, by `pubkey`, of the
+    /// spending transaction.
+    ///
+    /// An invalid signature or public key encoding is treated as a failed
+    /// signature check.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there is no transaction context, if
+    /// `WITNESS_PUBKEYTYPE` is set and a segwit v0 public key is not
+    /// compressed, or if `LOW_S` is set and the signature has a high S.
+    fn check_ecdsa_signature(&mut self, sig: &[u8], pubkey: &[u8], script_code: &Script) -> Result<bool, ScriptError> {
+        if self.sig_version == SigVersion::WitnessV0
+            && self.flags.contains(VerificationFlag::WitnessPubkeyType)
+            && !is_compressed_pubkey(pubkey)
+        {
+            return Err(ScriptError::WitnessPubkeyType);
+        }
+
+        let ctx = self.tx_context.ok_or(ScriptError::MissingTxContext)?;
+
+        // The last byte of the signature is the sighash type.
+        let (sighash_type, der) = match sig.split_last() {
+            Some((sighash_type, der)) => (*sighash_type, der),
+            None => return Ok(false),
+        };
+        let sig = match ecdsa::Signature::from_der_lax(der) {
+            Ok(sig) => sig,
+            Err(_) => return Ok(false),
+        };
+        if self.flags.contains(VerificationFlag::LowS) {
+            let mut normalized = sig;
+            normalized.normalize_s();
+            if normalized != sig {
+                return Err(ScriptError::SigHighS);
+            }
+        }
+        let pubkey = match PublicKey::from_slice(pubkey) {
+            Ok(pubkey) => pubkey,
+            Err(_) => return Ok(false),
+        };
+
+        let msg = self.sighash(ctx, script_code, u32::from(sighash_type))?;
+        Ok(verify(&pubkey, &sig, &msg))
+    }
+
+    /// Returns the message signed by an ECDSA signature with `sighash_type`,
+    /// reusing the message from an earlier signature check if possible.
+    ///
+    /// In legacy scripts `FindAndDelete` can change the script code
+    /// between checks so the message is only reused if the script code
+    /// is the same.
+    fn sighash(&mut self, ctx: &TxContext, script_code: &Script, sighash_type: u32) -> Result<Message, ScriptError> {
+        let key = (sighash_type, self.code_separator);
+        if let Some((cached_script_code, msg)) = self.sighash_cache.get(&key) {
+            if cached_script_code.as_script() == script_code {
+                return Ok(*msg);
+            }
+        }
+
+        let msg = ecdsa_sighash(ctx, script_code, sighash_type, self.sig_version)?;
+        self.sighashes_computed += 1;
+        self.sighash_cache.insert(key, (script_code.to_owned(), msg));
+        Ok(msg)
+    }
+
+    /// Removes the signature, number, and public key arguments to
+    /// `OP_CHECKSIGADD` and pushes the number, incremented by one if the
+    /// signature is not empty.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if not executing tapscript, if the stack does not
+    /// hold the arguments, if the number is not a valid 4 byte scriptint,
+    /// or if a non-empty signature is invalid.
+    fn check_sig_add(&mut self) -> Result<(), ScriptError> {
+        if self.sig_version != SigVersion::Tapscript {
+            return Err(ScriptError::BadOpcode);
+        }
+
+        let pubkey = self.stack.pop_bytes()?;
+        let n = self.pop_num()?;
+        let sig = self.stack.pop_bytes()?;
+
+        let success = self.check_tapscript_signature(&sig, &pubkey)?;
+        // `n` is at most 4 bytes so this can not overflow, the result may
+        // be 5 bytes which is valid on the stack.
+        self.stack.push_num(n + i64::from(success));
+        Ok(())
+    }
+
+    /// Checks a tapscript signature (BIP-342).
+    ///
+    /// Public keys that are not 32 bytes are an unknown type reserved for
+    /// future upgrades, signatures for these always succeed.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` if the signature is empty and `true` if it is valid.
+    /// Returns an error if the public key is empty, if the validation
+    /// budget is exceeded, if the signature is not empty but invalid, or
+    /// if the spent outputs are not known.
+    fn check_tapscript_signature(&mut self, sig: &[u8], pubkey: &[u8]) -> Result<bool, ScriptError> {
+        if pubkey.is_empty() {
+            return Err(ScriptError::PubkeyType);
+        }
+        if sig.is_empty() {
+            return Ok(false);
+        }
+        if let Some(ref mut budget) = self.validation_budget {
+            *budget -= VALIDATION_WEIGHT_PER_SIGOP_PASSED;
+            if *budget < 0 {
+                return Err(ScriptError::TapscriptValidationWeight);
+            }
+        }
+        if pubkey.len() != 32 {
+            if self.flags.contains(VerificationFlag::DiscourageUpgradablePubkeyType) {
+                return Err(ScriptError::DiscourageUpgradablePubkeyType);
+            }
+            return Ok(true);
+        }
+
+        let ctx = self.tx_context.ok_or(ScriptError::MissingTxContext)?;
+        let prevouts = ctx.prevouts().ok_or(ScriptError::MissingPrevouts)?;
+
+        let sig = parse_schnorr_signature(sig)?;
+        let pubkey = XOnlyPublicKey::from_slice(pubkey).map_err(|_| ScriptError::SchnorrSig)?;
+        let annex = taproot_annex(&ctx.input().witness);
+        let leaf_hash = hash::tap_leaf_hash(self.script.as_bytes(), LeafVersion::TapScript.to_consensus());
+        let leaf_hash = TapLeafHash::from_byte_array(leaf_hash);
+
+        let sighash = SighashCache::new(ctx.tx()).taproot_signature_hash(
+            ctx.input_index(),
+            &Prevouts::All(prevouts),
+            annex,
+            Some((leaf_hash, self.code_separator_index)),
+            sig.sighash_type,
+        )
+        // The prevouts and input index are checked by the context so this
+        // is `SIGHASH_SINGLE` without a corresponding output.
+        .map_err(|_| ScriptError::SchnorrSigHashtype)?;
+        let msg = Message::from_digest(sighash.to_byte_array());
+
+        if Secp256k1::verification_only().verify_schnorr(&sig.signature, &msg, &pubkey).is_err() {
+            return Err(ScriptError::SchnorrSig);
+        }
+        Ok(true)
+    }
+
+    /// Returns the script code used to create signature hashes, the
+    /// script after the last executed `OP_CODESEPARATOR`.
+    fn script_code(&self) -> ScriptBuf {
+        ScriptBuf::from_bytes(self.script.as_bytes()[self.code_separator..].to_vec())
+    }
+
+    /// Fails unless the lock time of the spending transaction satisfies
+    /// the lock time on top of the stack (BIP-65).
+    ///
+    /// The top stack item is left on the stack.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there is no transaction context, if the stack
+    /// is empty, if the lock time is not satisfied, or if the context has
+    /// a block height or median time past and the transaction is not
+    /// final.
+    fn check_lock_time_verify(&mut self) -> Result<(), ScriptError> {
+        let ctx = match self.tx_context {
+            Some(ctx) => ctx,
+            None => return Err(ScriptError::MissingTxContext),
+        };
+
+        self.check_minimal_num()?;
+        let lock_time = self.stack.top_num_with_max_size(LOCK_TIME_NUM_SIZE)?;
+        if lock_time < 0 {
+            return Err(ScriptError::NegativeLocktime);
+        }
+
+        let threshold = i64::from(LOCK_TIME_THRESHOLD);
+        let tx_lock_time = i64::from(ctx.tx().lock_time.to_consensus_u32());
+        // Block height and block time lock times can not be compared.
+        if (lock_time < threshold) != (tx_lock_time < threshold) {
+            return Err(ScriptError::UnsatisfiedLocktime);
+        }
+        if lock_time > tx_lock_time {
+            return Err(ScriptError::UnsatisfiedLocktime);
+        }
+        // If the chain state is known the transaction must also be final
+        // i.e., its lock time must have passed.
+        let chain = if tx_lock_time < threshold { ctx.block_height() } else { ctx.median_time_past() };
+        if let Some(chain) = chain {
+            if tx_lock_time >= i64::from(chain) {
+                return Err(ScriptError::UnsatisfiedLocktime);
+            }
+        }
+        // A final sequence number disables nLockTime for the transaction
+        // so the lock time could be bypassed.
+        if ctx.input().sequence == Sequence::MAX {
+            return Err(ScriptError::UnsatisfiedLocktime);
+        }
+        Ok(())
+    }
+
+    /// Fails unless the sequence number of the input being validated
+    /// satisfies the relative lock time on top of the stack (BIP-112).
+    ///
+    /// The top stack item is left on the stack.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there is no transaction context, if the stack
+    /// is empty, if the relative lock time is not satisfied, or if the
+    /// context has the chain state for the spent output and the relative
+    /// lock time of the input has not passed.
+    fn check_sequence_verify(&mut self) -> Result<(), ScriptError> {
+        let ctx = match self.tx_context {
+            Some(ctx) => ctx,
+            None => return Err(ScriptError::MissingTxContext),
+        };
+
+        self.check_minimal_num()?;
+        let sequence = self.stack.top_num_with_max_size(LOCK_TIME_NUM_SIZE)?;
+        if sequence < 0 {
+            return Err(ScriptError::NegativeLocktime);
+        }
+        let sequence = sequence as u32;
+        // With the disable flag set OP_CHECKSEQUENCEVERIFY is a NOP.
+        if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return Ok(());
+        }
+
+        if ctx.tx().version < transaction::Version::TWO {
+            return Err(ScriptError::UnsatisfiedLocktime);
+        }
+        let tx_sequence = ctx.input().sequence.to_consensus_u32();
+        if tx_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return Err(ScriptError::UnsatisfiedLocktime);
+        }
+
+        let mask = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+        let (sequence, tx_sequence) = (sequence & mask, tx_sequence & mask);
+        // Block height and block time relative lock times can not be compared.
+        if (sequence < SEQUENCE_LOCKTIME_TYPE_FLAG) != (tx_sequence < SEQUENCE_LOCKTIME_TYPE_FLAG) {
+            return Err(ScriptError::UnsatisfiedLocktime);
+        }
+        if sequence > tx_sequence {
+            return Err(ScriptError::UnsatisfiedLocktime);
+        }
+        // If the chain state is known the relative lock time of the input
+        // must also have passed (BIP-68), time is in units of 512 seconds.
+        let lock = tx_sequence & SEQUENCE_LOCKTIME_MASK;
+        let (chain, lock) = if tx_sequence < SEQUENCE_LOCKTIME_TYPE_FLAG {
+            (ctx.block_height().zip(ctx.prevout_height()), lock)
+        } else {
+            (ctx.median_time_past().zip(ctx.prevout_median_time_past()), lock << 9)
+        };
+        if let Some((now, confirmed)) = chain {
+            if now.saturating_sub(confirmed) < lock {
+                return Err(ScriptError::UnsatisfiedLocktime);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds the message signed by an ECDSA signature, over `script_code`,
+/// for the input being validated in `ctx`.
+///
+/// # Returns
+///
+/// Returns an error if `sig_version` is [`SigVersion::Tapscript`], or if
+/// `sig_version` is [`SigVersion::WitnessV0`] and `ctx` does not include
+/// the prevouts.
+pub fn build_sighash(
+    ctx: &TxContext,
+    script_code: &Script,
+    sighash_type: u32,
+    sig_version: SigVersion,
+) -> anyhow::Result<Message> {
+    if sig_version == SigVersion::Tapscript {
+        anyhow::bail!("tapscript signatures are not ECDSA");
+    }
+    Ok(ecdsa_sighash(ctx, script_code, sighash_type, sig_version)?)
+}
+
+/// Same as [`build_sighash`], `sig_version` must not be [`SigVersion::Tapscript`].
+fn ecdsa_sighash(
+    ctx: &TxContext,
+    script_code: &Script,
+    sighash_type: u32,
+    sig_version: SigVersion,
+) -> Result<Message, ScriptError> {
+    let mut cache = SighashCache::new(ctx.tx());
+    // The input index is checked by the context so computing the
+    // signature hash can not fail.
+    let sighash = match sig_version {
+        SigVersion::Base => cache
+            .legacy_signature_hash(ctx.input_index(), script_code, sighash_type)
+            .expect("input index is in range")
+            .to_byte_array(),
+        SigVersion::WitnessV0 => {
+            // Segwit v0 signatures commit to the amount being spent.
+            let prevouts = ctx.prevouts().ok_or(ScriptError::MissingPrevouts)?;
+            let amount = prevouts[ctx.input_index()].value;
+            cache
+                .p2wsh_signature_hash(ctx.input_index(), script_code, amount, EcdsaSighashType::from_consensus(sighash_type))
+                .expect("input index is in range")
+                .to_byte_array()
+        }
+        SigVersion::Tapscript => unreachable!("tapscript signatures are not ECDSA"),
+    };
+    Ok(Message::from_digest(sighash))
+}
+
+/// Verifies a taproot key path spend of the output with `output_key`
+/// by the input being validated in `ctx` (BIP-341).
+///
+/// The witness, excluding the annex, must be a single Schnorr signature
+/// by the output key, no script is executed.
+///
+/// # Returns
+///
+/// Returns `true` if the signature is valid. Returns an error if the
+/// signature is invalid, if `ctx` does not include the prevouts, if the
+/// witness is empty, or if the witness is for a script path spend.
+pub(crate) fn verify_taproot_key_path(output_key: &[u8], ctx: &TxContext) -> Result<bool, ScriptError> {
+    let prevouts = ctx.prevouts().ok_or(ScriptError::MissingPrevouts)?;
+    let witness = &ctx.input().witness;
+    let annex = taproot_annex(witness);
+    match witness.len() - usize::from(annex.is_some()) {
+        0 => return Err(ScriptError::WitnessProgramWitnessEmpty),
+        1 => {}
+        // TODO: Execute taproot script path spends, verify the control block
+        // then run the leaf script with `crate::execute_tapscript`.
+        _ => return Err(ScriptError::UnsupportedWitnessSpend),
+    }
+
+    let sig = parse_schnorr_signature(&witness[0])?;
+    let pubkey = XOnlyPublicKey::from_slice(output_key).map_err(|_| ScriptError::SchnorrSig)?;
+    let sighash = SighashCache::new(ctx.tx()).taproot_signature_hash(
+        ctx.input_index(),
+        &Prevouts::All(prevouts),
+        annex,
+        None,
+        sig.sighash_type,
+    )
+    // The prevouts and input index are checked by the context so this is
+    // `SIGHASH_SINGLE` without a corresponding output.
+    .map_err(|_| ScriptError::SchnorrSigHashtype)?;
+    let msg = Message::from_digest(sighash.to_byte_array());
+    Secp256k1::verification_only().verify_schnorr(&sig.signature, &msg, &pubkey).map_err(|_| ScriptError::SchnorrSig)?;
+    Ok(true)
+}
+
+/// Parses a taproot signature, a 64 byte Schnorr signature optionally
+/// followed by the sighash type.
+fn parse_schnorr_signature(sig: &[u8]) -> Result<taproot::Signature, ScriptError> {
+    if sig.len() != 64 && sig.len() != 65 {
+        return Err(ScriptError::SchnorrSigSize);
+    }
+    // With the size checked only the sighash type can be invalid.
+    taproot::Signature::from_slice(sig).map_err(|_| ScriptError::SchnorrSigHashtype)
+}
+
+/// Returns the annex of the taproot spend with `witness`, if there is one.
+fn taproot_annex(witness: &Witness) -> Option<Annex<'_>> {
+    // `taproot_annex` only returns items starting with the annex prefix.
+    witness.taproot_annex().map(|annex| Annex::new(annex).expect("item starts with the annex prefix"))
+}
+
+/// Returns `true` if `sig` is a valid signature of `msg` by `pubkey`.
+///
+/// High S signatures are normalized before verification.
+pub fn verify(pubkey: &PublicKey, sig: &ecdsa::Signature, msg: &Message) -> bool {
+    let mut sig = *sig;
+    // libsecp256k1 only verifies low S signatures.
+    sig.normalize_s();
+    Secp256k1::verification_only().verify_ecdsa(msg, &sig, pubkey).is_ok()
+}
+
+/// Returns `true` if `op` is one of the opcodes disabled in 2010.
+///
+/// A script containing a disabled opcode fails, even if the opcode is in
+/// a branch that is not executed.
+pub(crate) fn is_disabled(op: Opcode) -> bool {
+    matches!(
+        op,
+        OP_CAT
+            | OP_SUBSTR
+            | OP_LEFT
+            | OP_RIGHT
+            | OP_INVERT
+            | OP_AND
+            | OP_OR
+            | OP_XOR
+            | OP_2MUL
+            | OP_2DIV
+            | OP_MUL
+            | OP_DIV
+            | OP_MOD
+            | OP_LSHIFT
+            | OP_RSHIFT
+    )
+}
+
+/// Returns `true` if the interpreter can execute `op`.
+///
+/// Disabled opcodes are supported in the sense that executing them
+/// fails as it should, executing any other opcode that is not supported
+/// fails with [`ScriptError::UnsupportedOpcode`]. Checked against
+/// `execute_opcode` by the `is_supported_matches_execution` test.
+pub(crate) fn is_supported(op: Opcode) -> bool {
+    is_disabled(op)
+        || matches!(
+            op,
+            OP_IF
+                | OP_NOTIF
+                | OP_VERIF
+                | OP_VERNOTIF
+                | OP_ELSE
+                | OP_ENDIF
+                | OP_RETURN
+                | OP_PUSHNUM_NEG1
+                | OP_PUSHBYTES_0
+                | OP_PUSHNUM_1
+                | OP_PUSHNUM_2
+                | OP_PUSHNUM_3
+                | OP_PUSHNUM_4
+                | OP_PUSHNUM_5
+                | OP_PUSHNUM_6
+                | OP_PUSHNUM_7
+                | OP_PUSHNUM_8
+                | OP_PUSHNUM_9
+                | OP_PUSHNUM_10
+                | OP_PUSHNUM_11
+                | OP_PUSHNUM_12
+                | OP_PUSHNUM_13
+                | OP_PUSHNUM_14
+                | OP_PUSHNUM_15
+                | OP_PUSHNUM_16
+                | OP_VERIFY
+                | OP_TOALTSTACK
+                | OP_FROMALTSTACK
+                | OP_2DROP
+                | OP_IFDUP
+                | OP_DROP
+                | OP_DUP
+                | OP_NIP
+                | OP_2DUP
+                | OP_3DUP
+                | OP_OVER
+                | OP_PICK
+                | OP_ROLL
+                | OP_ROT
+                | OP_SWAP
+                | OP_TUCK
+                | OP_ADD
+                | OP_SUB
+                | OP_NUMEQUAL
+                | OP_NUMNOTEQUAL
+                | OP_LESSTHAN
+                | OP_GREATERTHAN
+                | OP_LESSTHANOREQUAL
+                | OP_GREATERTHANOREQUAL
+                | OP_EQUAL
+                | OP_EQUALVERIFY
+                | OP_RIPEMD160
+                | OP_SHA1
+                | OP_SHA256
+                | OP_HASH160
+                | OP_HASH256
+                | OP_CODESEPARATOR
+                | OP_CHECKSIG
+                | OP_CHECKSIGVERIFY
+                | OP_CHECKSIGADD
+                | OP_CHECKMULTISIG
+                | OP_CHECKMULTISIGVERIFY
+                | OP_CLTV
+                | OP_CSV
+        )
+}
+
+/// Returns the tapscript validation budget for an input spent with `witness` (BIP-342).
+///
+/// The budget is 50 plus the serialized size of the witness stack,
+/// including the annex and the element count.
+pub fn validation_budget(witness: &Witness) -> i64 {
+    VALIDATION_WEIGHT_OFFSET + consensus::serialize(witness).len() as i64
+}
+
+/// Returns `true` if `op` is one of the `OP_SUCCESSx` opcodes that make a
+/// tapscript succeed unconditionally (BIP-342).
+pub(crate) fn is_op_success(op: Opcode) -> bool {
+    matches!(op.to_u8(), 80 | 98 | 126..=129 | 131..=134 | 137..=138 | 141..=142 | 149..=153 | 187..=254)
+}
+
+/// Removes all pushes of `data` from `script` (Bitcoin Core's `FindAndDelete`).
+fn find_and_delete(script: &Script, data: &[u8]) -> ScriptBuf {
+    let pattern = push_encoding(data);
+    let bytes = script.as_bytes();
+
+    let mut result = Vec::with_capacity(bytes.len());
+    let (mut pc, mut copied) = (0, 0);
+    loop {
+        result.extend_from_slice(&bytes[copied..pc]);
+        while bytes[pc..].starts_with(&pattern) {
+            pc += pattern.len();
+        }
+        copied = pc;
+        match instruction_len(&bytes[pc..]) {
+            Some(len) => pc += len,
+            None => break,
+        }
+    }
+    result.extend_from_slice(&bytes[copied..]);
+    ScriptBuf::from_bytes(result)
+}
+
+/// Returns `true` if `pubkey` is encoded as a compressed public key.
+fn is_compressed_pubkey(pubkey: &[u8]) -> bool {
+    pubkey.len() == 33 && (pubkey[0] == 0x02 || pubkey[0] == 0x03)
+}
+
+/// Returns `true` if `bytes` is the minimal encoding of a scriptint.
+///
+/// The last byte may only be zero, except for the sign bit, if the byte
+/// before it needs its most significant bit to hold the number.
+fn is_minimal_num(bytes: &[u8]) -> bool {
+    match bytes {
+        [] => true,
+        [.., last] if last & 0x7f != 0 => true,
+        [_] => false,
+        [.., before, _] => before & 0x80 != 0,
+    }
+}
+
+/// Returns `true` if `opcode` is the smallest push opcode that pushes `data`.
+fn is_minimal_push(opcode: u8, data: &[u8]) -> bool {
+    match data.len() {
+        0 => opcode == OP_PUSHBYTES_0.to_u8(),
+        1 if (1..=16).contains(&data[0]) => opcode == OP_PUSHNUM_1.to_u8() + data[0] - 1,
+        1 if data[0] == 0x81 => opcode == OP_PUSHNUM_NEG1.to_u8(),
+        len if len < usize::from(OP_PUSHDATA1.to_u8()) => usize::from(opcode) == len,
+        len if len <= 0xff => opcode == OP_PUSHDATA1.to_u8(),
+        len if len <= 0xffff => opcode == OP_PUSHDATA2.to_u8(),
+        _ => true,
+    }
+}
+
+/// Returns the script bytes that push `data` using the smallest push opcode.
+pub(crate) fn push_encoding(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len() + 5);
+    let len = data.len();
+    if len < usize::from(OP_PUSHDATA1.to_u8()) {
+        encoded.push(len as u8);
+    } else if len <= 0xff {
+        encoded.push(OP_PUSHDATA1.to_u8());
+        encoded.push(len as u8);
+    } else if len <= 0xffff {
+        encoded.push(OP_PUSHDATA2.to_u8());
+        encoded.extend_from_slice(&(len as u16).to_le_bytes());
+    } else {
+        encoded.push(OP_PUSHDATA4.to_u8());
+        encoded.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+    encoded.extend_from_slice(data);
+    encoded
+}
+
+/// Returns the length in bytes of the instruction at the start of `bytes`.
+///
+/// Returns `None` if `bytes` is empty or starts with a truncated push.
+fn instruction_len(bytes: &[u8]) -> Option<usize> {
+    let op = *bytes.first()?;
+    let (prefix_len, data_len) = if op < OP_PUSHDATA1.to_u8() {
+        (1, usize::from(op))
+    } else if op == OP_PUSHDATA1.to_u8() {
+        (2, usize::from(*bytes.get(1)?))
+    } else if op == OP_PUSHDATA2.to_u8() {
+        (3, usize::from(u16::from_le_bytes(bytes.get(1..3)?.try_into().ok()?)))
+    } else if op == OP_PUSHDATA4.to_u8() {
+        (5, u32::from_le_bytes(bytes.get(1..5)?.try_into().ok()?) as usize)
+    } else {
+        (1, 0)
+    };
+
+    let len = prefix_len + data_len;
+    if len > bytes.len() {
+        return None;
+    }
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::hex_to_bytes;
+    use bitcoin::script::{Builder, PushBytesBuf, ScriptExt};
+    use bitcoin::secp256k1::Keypair;
+    use bitcoin::sighash::TapSighashType;
+    use bitcoin::{absolute, transaction, Amount, OutPoint, Script, Transaction, TxIn, TxOut, Witness};
+
+    /// Creates a single input transaction with the given lock time and input sequence.
+    fn spending_tx(lock_time: u32, sequence: Sequence) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::from_consensus(lock_time),
+            input: vec![TxIn {
+                previous_output: OutPoint::COINBASE_PREVOUT,
+                script_sig: ScriptBuf::new(),
+                sequence,
+                witness: Witness::new(),
+            }],
+            output: vec![],
+        }
+    }
+
+    #[test]
+    fn op_return_false_no_data() {
+        let script = Script::builder().push_opcode(OP_RETURN).into_script();
+        assert!(Interpreter::new(script.clone()).execute_script().is_err());
+        assert!(!Interpreter::new(script).script_is_valid());
+    }
+
+    #[test]
+    fn op_return_false_with_data() {
+        let script = Script::builder()
+            .push_opcode(OP_RETURN)
+            .push_slice(&[0xab, 32]) // Just push some nonsense.
+            .into_script();
+
+        assert!(Interpreter::new(script.clone()).execute_script().is_err());
+        assert!(!Interpreter::new(script).script_is_valid());
+    }
+
+    #[test]
+    fn add() {
+        let script = Script::builder()
+            .push_int(5).unwrap()
+            .push_int(2).unwrap()
+            .push_int(3).unwrap()
+            .push_opcode(OP_ADD)
+            .push_opcode(OP_EQUAL)
+            .into_script();
+
+        assert!(Interpreter::new(script).script_is_valid());
+    }
+
+    #[test]
+    fn sub() {
+        let script = Script::builder()
+            .push_int(2).unwrap()
+            .push_int(5).unwrap()
+            .push_int(3).unwrap()
+            .push_opcode(OP_SUB)
+            .push_opcode(OP_EQUAL)
+            .into_script();
+
+        assert!(Interpreter::new(script).script_is_valid());
+    }
+
+    #[test]
+    fn add_overflows_to_five_bytes() {
+        let script = Script::builder()
+            .push_int(i64::from(i32::MAX)).unwrap()
+            .push_int(i64::from(i32::MAX)).unwrap()
+            .push_opcode(OP_ADD)
+            .push_slice([0xfe, 0xff, 0xff, 0xff, 0x00])
+            .push_opcode(OP_EQUAL)
+            .into_script();
+
+        assert!(Interpreter::new(script).script_is_valid());
+    }
+
+    #[test]
+    fn sub_overflows_to_five_bytes() {
+        let script = Script::builder()
+            .push_int(-i64::from(i32::MAX)).unwrap()
+            .push_int(i64::from(i32::MAX)).unwrap()
+            .push_opcode(OP_SUB)
+            .push_slice([0xfe, 0xff, 0xff, 0xff, 0x80])
+            .push_opcode(OP_EQUAL)
+            .into_script();
+
+        assert!(Interpreter::new(script).script_is_valid());
+    }
+
+    #[test]
+    fn five_byte_operand_fails() {
+        let script = Script::builder()
+            .push_int(i64::from(i32::MAX)).unwrap()
+            .push_int(i64::from(i32::MAX)).unwrap()
+            .push_opcode(OP_ADD)
+            .push_int(1).unwrap()
+            .push_opcode(OP_ADD)
+            .into_script();
+
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::InvalidScriptInt);
+    }
+
+    #[test]
+    fn op_return_is_early_return() {
+        let script = Script::builder().push_int(1).unwrap().push_opcode(OP_RETURN).into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+
+        assert_eq!(err, ScriptError::EarlyReturn);
+    }
+
+    #[test]
+    fn unsupported_opcode_fails() {
+        let script = Script::builder().push_int(1).unwrap().push_opcode(OP_NEGATE).into_script();
+        assert!(!is_supported(OP_NEGATE));
+
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::UnsupportedOpcode(OP_NEGATE));
+    }
+
+    #[test]
+    fn is_supported_matches_execution() {
+        // Every opcode that is not a data push, with enough items on the
+        // stack that no opcode fails for lack of arguments.
+        for byte in OP_PUSHNUM_NEG1.to_u8()..=u8::MAX {
+            let op = Opcode::from(byte);
+            let script = push_n(20).push_opcode(op).into_script();
+            let unsupported = Interpreter::new(script).execute_script() == Err(ScriptError::UnsupportedOpcode(op));
+
+            assert_eq!(is_supported(op), !unsupported, "{}", op);
+        }
+    }
+
+    #[test]
+    fn cltv_non_final_sequence() {
+        let script = Script::builder().push_int(400).unwrap().push_opcode(OP_CLTV).into_script();
+        let ctx = TxContext::new(spending_tx(500, Sequence::ENABLE_LOCKTIME_NO_RBF), 0).unwrap();
+
+        assert!(Interpreter::new(script).with_tx_context(&ctx).script_is_valid());
+    }
+
+    #[test]
+    fn cltv_final_sequence() {
+        let script = Script::builder().push_int(400).unwrap().push_opcode(OP_CLTV).into_script();
+        let ctx = TxContext::new(spending_tx(500, Sequence::MAX), 0).unwrap();
+
+        let err = Interpreter::new(script).with_tx_context(&ctx).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::UnsatisfiedLocktime);
+    }
+
+    #[test]
+    fn cltv_block_height() {
+        let script = Script::builder().push_int(500).unwrap().push_opcode(OP_CLTV).into_script();
+        let tx = spending_tx(500, Sequence::ENABLE_LOCKTIME_NO_RBF);
+
+        let ctx = TxContext::new(tx.clone(), 0).unwrap().with_block_height(501);
+        assert!(Interpreter::new(script.clone()).with_tx_context(&ctx).script_is_valid());
+
+        let ctx = TxContext::new(tx, 0).unwrap().with_block_height(500);
+        let err = Interpreter::new(script).with_tx_context(&ctx).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::UnsatisfiedLocktime);
+    }
+
+    #[test]
+    fn cltv_median_time_past() {
+        let lock_time = LOCK_TIME_THRESHOLD + 1000;
+        let script = Script::builder().push_int(i64::from(lock_time)).unwrap().push_opcode(OP_CLTV).into_script();
+        let tx = spending_tx(lock_time, Sequence::ENABLE_LOCKTIME_NO_RBF);
+
+        // The block height does not apply to a time lock.
+        let ctx = TxContext::new(tx.clone(), 0).unwrap().with_block_height(1).with_median_time_past(lock_time + 1);
+        assert!(Interpreter::new(script.clone()).with_tx_context(&ctx).script_is_valid());
+
+        let ctx = TxContext::new(tx, 0).unwrap().with_median_time_past(lock_time);
+        let err = Interpreter::new(script).with_tx_context(&ctx).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::UnsatisfiedLocktime);
+    }
+
+    #[test]
+    fn cltv_five_byte_lock_time() {
+        // 0x80000000 needs a 5 byte scriptint.
+        let script = Script::builder().push_slice(&[0x00, 0x00, 0x00, 0x80, 0x00]).push_opcode(OP_CLTV).into_script();
+
+        let ctx = TxContext::new(spending_tx(0x8000_0001, Sequence::ENABLE_LOCKTIME_NO_RBF), 0).unwrap();
+        assert!(Interpreter::new(script.clone()).with_tx_context(&ctx).script_is_valid());
+
+        let ctx = TxContext::new(spending_tx(0x7fff_ffff, Sequence::ENABLE_LOCKTIME_NO_RBF), 0).unwrap();
+        let err = Interpreter::new(script).with_tx_context(&ctx).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::UnsatisfiedLocktime);
+    }
+
+    #[test]
+    fn negative_lock_time() {
+        let ctx = TxContext::new(spending_tx(0, Sequence::ZERO), 0).unwrap();
+        for op in [OP_CLTV, OP_CSV] {
+            let script = Script::builder().push_int(-1).unwrap().push_opcode(op).into_script();
+            let err = Interpreter::new(script).with_tx_context(&ctx).execute_script().unwrap_err();
+
+            assert_eq!(err, ScriptError::NegativeLocktime);
+        }
+    }
+
+    #[test]
+    fn csv_relative_height() {
+        let script = Script::builder().push_int(10).unwrap().push_opcode(OP_CSV).into_script();
+
+        let ctx = TxContext::new(spending_tx(0, Sequence::from_height(10)), 0).unwrap();
+        assert!(Interpreter::new(script.clone()).with_tx_context(&ctx).script_is_valid());
+
+        let ctx = TxContext::new(spending_tx(0, Sequence::from_height(9)), 0).unwrap();
+        let err = Interpreter::new(script).with_tx_context(&ctx).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::UnsatisfiedLocktime);
+    }
+
+    #[test]
+    fn csv_block_height() {
+        let script = Script::builder().push_int(10).unwrap().push_opcode(OP_CSV).into_script();
+        let tx = spending_tx(0, Sequence::from_height(10));
+
+        let ctx = TxContext::new(tx.clone(), 0).unwrap().with_prevout_height(100).with_block_height(110);
+        assert!(Interpreter::new(script.clone()).with_tx_context(&ctx).script_is_valid());
+
+        let ctx = TxContext::new(tx, 0).unwrap().with_prevout_height(100).with_block_height(109);
+        let err = Interpreter::new(script).with_tx_context(&ctx).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::UnsatisfiedLocktime);
+    }
+
+    #[test]
+    fn csv_median_time_past() {
+        let lock = i64::from(SEQUENCE_LOCKTIME_TYPE_FLAG | 2);
+        let script = Script::builder().push_int(lock).unwrap().push_opcode(OP_CSV).into_script();
+        let tx = spending_tx(0, Sequence::from_512_second_intervals(2));
+
+        // The block height does not apply to a time relative lock time.
+        let ctx = TxContext::new(tx.clone(), 0)
+            .unwrap()
+            .with_prevout_height(100)
+            .with_block_height(100)
+            .with_prevout_median_time_past(1000)
+            .with_median_time_past(2024);
+        assert!(Interpreter::new(script.clone()).with_tx_context(&ctx).script_is_valid());
+
+        let ctx = TxContext::new(tx, 0).unwrap().with_prevout_median_time_past(1000).with_median_time_past(2023);
+        let err = Interpreter::new(script).with_tx_context(&ctx).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::UnsatisfiedLocktime);
+    }
+
+    /// Pushes `1` and `1` padded with a zero byte, a non-minimal encoding of the same number.
+    fn padded_one() -> Builder {
+        Script::builder().push_slice(&[0x01]).push_slice(&[0x01, 0x00])
+    }
+
+    #[test]
+    fn bytes_equal_padded_number() {
+        let script = padded_one().push_opcode(OP_EQUAL).into_script();
+
+        assert!(!Interpreter::new(script).script_is_valid());
+    }
+
+    #[test]
+    fn num_equal_padded_number() {
+        let script = padded_one().push_opcode(OP_NUMEQUAL).into_script();
+
+        assert!(Interpreter::new(script).script_is_valid());
+    }
+
+    /// Executes `<a> <b> <op>` returning the result.
+    fn compare(a: i64, b: i64, op: Opcode) -> bool {
+        let script = Script::builder().push_int(a).unwrap().push_int(b).unwrap().push_opcode(op).into_script();
+        Interpreter::new(script).execute_script().unwrap()
+    }
+
+    #[test]
+    fn numeric_comparisons() {
+        assert!(compare(3, 2, OP_GREATERTHAN));
+        assert!(!compare(2, 3, OP_GREATERTHAN));
+        assert!(compare(2, 3, OP_LESSTHAN));
+        assert!(!compare(3, 3, OP_LESSTHAN));
+        assert!(compare(3, 3, OP_LESSTHANOREQUAL));
+        assert!(!compare(4, 3, OP_LESSTHANOREQUAL));
+        assert!(compare(3, 3, OP_GREATERTHANOREQUAL));
+        assert!(!compare(-1, 3, OP_GREATERTHANOREQUAL));
+        assert!(compare(2, 3, OP_NUMNOTEQUAL));
+        assert!(!compare(3, 3, OP_NUMNOTEQUAL));
+    }
+
+    #[test]
+    fn num_equal_verify() {
+        let script = Script::builder()
+            .push_int(2).unwrap()
+            .push_int(2).unwrap()
+            .push_opcode(OP_NUMEQUAL)
+            .push_opcode(OP_VERIFY)
+            .push_int(1).unwrap()
+            .into_script();
+        let mut interpreter = Interpreter::new(script);
+
+        assert!(interpreter.execute_script().unwrap());
+        assert_eq!(interpreter.stack.items(), &[vec![0x01]]);
+    }
+
+    #[test]
+    fn verify_false_fails() {
+        let script = Script::builder().push_int(0).unwrap().push_opcode(OP_VERIFY).into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::Verify);
+
+        let script = Script::builder().push_int(1).unwrap().push_int(2).unwrap().push_opcode(OP_EQUALVERIFY).into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::EqualVerify);
+    }
+
+    #[test]
+    fn failed_signature_verify() {
+        let ctx = TxContext::new(spending_tx(0, Sequence::MAX), 0).unwrap();
+
+        // An empty signature always fails.
+        let script = Script::builder()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_slice([0x02; 33])
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .into_script();
+        let err = Interpreter::new(script).with_tx_context(&ctx).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::CheckSigVerify);
+
+        let script = Script::builder()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_int(1).unwrap()
+            .push_slice([0x02; 33])
+            .push_int(1).unwrap()
+            .push_opcode(OP_CHECKMULTISIGVERIFY)
+            .into_script();
+        let err = Interpreter::new(script).with_tx_context(&ctx).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::CheckMultisigVerify);
+    }
+
+    #[test]
+    fn multisig_counts_out_of_range() {
+        let script = Script::builder().push_int(21).unwrap().push_opcode(OP_CHECKMULTISIG).into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::PubkeyCount);
+
+        // A 2-of-1 multisig.
+        let script = Script::builder()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_int(2).unwrap()
+            .push_slice([0x02; 33])
+            .push_int(1).unwrap()
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::SigCount);
+    }
+
+    #[test]
+    fn bad_opcodes() {
+        // OP_VERIF fails even in a branch that is not executed.
+        let script = Script::builder()
+            .push_int(0).unwrap()
+            .push_opcode(OP_IF)
+            .push_opcode(OP_VERIF)
+            .push_opcode(OP_ENDIF)
+            .push_int(1).unwrap()
+            .into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::BadOpcode);
+
+        let script = Script::builder()
+            .push_int(0).unwrap()
+            .push_int(0).unwrap()
+            .push_slice([0xab; 32])
+            .push_opcode(OP_CHECKSIGADD)
+            .into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::BadOpcode);
+    }
+
+    #[test]
+    fn verify_on_empty_stack_fails() {
+        let script = Script::builder().push_opcode(OP_VERIFY).into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+
+        assert_eq!(err, ScriptError::StackUnderflow { opcode: OP_VERIFY });
+    }
+
+    /// Returns a builder for a script that pushes `n` items onto the stack.
+    fn push_n(n: usize) -> Builder {
+        (0..n).fold(Script::builder(), |builder, _| builder.push_opcode(OP_PUSHNUM_1))
+    }
+
+    #[test]
+    fn dup_to_stack_size_limit() {
+        let script = push_n(MAX_STACK_SIZE - 1).push_opcode(OP_DUP).into_script();
+
+        assert!(Interpreter::new(script).script_is_valid());
+    }
+
+    #[test]
+    fn dup_exceeds_stack_size_limit() {
+        let script = push_n(MAX_STACK_SIZE).push_opcode(OP_DUP).into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+
+        assert_eq!(err, ScriptError::StackSize);
+    }
+
+    #[test]
+    fn if_dup_exceeds_stack_size_limit() {
+        let script = push_n(MAX_STACK_SIZE).push_opcode(OP_IFDUP).into_script();
+
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::StackSize);
+    }
+
+    #[test]
+    fn dup2_exceeds_stack_size_limit() {
+        let script = push_n(MAX_STACK_SIZE - 1).push_opcode(OP_2DUP).into_script();
+
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::StackSize);
+    }
+
+    /// Returns a script that checks executing `op` on the empty string produces `digest`.
+    fn hash_of_empty(op: Opcode, digest: &str) -> ScriptBuf {
+        let digest = PushBytesBuf::try_from(hex_to_bytes(digest)).unwrap();
+        Script::builder()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_opcode(op)
+            .push_slice(digest)
+            .push_opcode(OP_EQUAL)
+            .into_script()
+    }
+
+    #[test]
+    fn hash_opcodes() {
+        let vectors = [
+            (OP_RIPEMD160, "9c1185a5c5e9fc54612808977ee8f548b2258d31"),
+            (OP_SHA1, "da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+            (OP_SHA256, "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+            (OP_HASH160, "b472a266d0bd89c13706a4132ccfb16f7c3b9fcb"),
+            (OP_HASH256, "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456"),
+        ];
+        for (op, digest) in vectors {
+            assert!(Interpreter::new(hash_of_empty(op, digest)).script_is_valid(), "{}", op);
+        }
+    }
+
+    /// Returns a builder for a 0-of-0 multisig script with `dummy` as the dummy element.
+    fn multisig_with_dummy(dummy: &[u8]) -> Builder {
+        let dummy = PushBytesBuf::try_from(dummy.to_vec()).unwrap();
+        Script::builder()
+            .push_slice(dummy)
+            .push_int(0).unwrap()
+            .push_int(0).unwrap()
+            .push_opcode(OP_CHECKMULTISIG)
+    }
+
+    #[test]
+    fn multisig_null_dummy() {
+        let script = multisig_with_dummy(&[]).into_script();
+
+        assert!(Interpreter::new(script).script_is_valid());
+    }
+
+    #[test]
+    fn multisig_non_null_dummy() {
+        let script = multisig_with_dummy(&[0x01]).into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+
+        assert_eq!(err, ScriptError::SigNullDummy);
+    }
+
+    #[test]
+    fn multisig_non_null_dummy_without_flag() {
+        let script = multisig_with_dummy(&[0x01]).into_script();
+        let flags = VerificationFlags::CONSENSUS.without(VerificationFlag::NullDummy);
+
+        assert!(Interpreter::new(script).with_flags(flags).script_is_valid());
+    }
+
+    #[test]
+    fn find_and_delete_removes_pushes() {
+        let sig = [0xaa, 0xbb];
+        let script = Script::builder()
+            .push_slice(&sig)
+            .push_opcode(OP_DUP)
+            .push_slice(&sig)
+            .push_slice(&sig)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let want = Script::builder().push_opcode(OP_DUP).push_opcode(OP_CHECKSIG).into_script();
+
+        assert_eq!(find_and_delete(&script, &sig), want);
+    }
+
+    #[test]
+    fn check_sig_add_large_accumulator() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[0x01; 32]).unwrap();
+        let (pubkey, _) = keypair.x_only_public_key();
+
+        // 0x7fffffff + 1 needs a 5 byte scriptint.
+        let script = Script::builder()
+            .push_int(0x7fff_ffff).unwrap()
+            .push_slice(pubkey.serialize())
+            .push_opcode(OP_CHECKSIGADD)
+            .push_slice(&[0x00, 0x00, 0x00, 0x80, 0x00])
+            .push_opcode(OP_EQUAL)
+            .into_script();
+
+        let tx = spending_tx(0, Sequence::MAX);
+        let prevouts = vec![TxOut { value: Amount::ONE_BTC, script_pubkey: ScriptBuf::new() }];
+        let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+        let sighash = SighashCache::new(&tx)
+            .taproot_signature_hash(0, &Prevouts::All(&prevouts), None, Some((leaf_hash, u32::MAX)), TapSighashType::Default)
+            .unwrap();
+        let sig = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), &keypair);
+        let ctx = TxContext::new(tx, 0).unwrap().with_prevouts(prevouts).unwrap();
+
+        let mut stack = Stack::new();
+        stack.push(&sig.serialize());
+        let mut interpreter = Interpreter::new(script).with_sig_version(SigVersion::Tapscript).with_tx_context(&ctx);
+
+        assert!(interpreter.execute_script_with_stack(stack).unwrap());
+    }
+
+    #[test]
+    fn sighash_computed_once_per_sighash_type() {
+        let secp = Secp256k1::new();
+        let sk = bitcoin::secp256k1::SecretKey::from_slice(&[0x01; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&secp, &sk);
+        let script = Script::builder()
+            .push_opcode(OP_DUP)
+            .push_slice(pubkey.serialize())
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_slice(pubkey.serialize())
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let ctx = TxContext::new(spending_tx(0, Sequence::MAX), 0).unwrap();
+        let msg = build_sighash(&ctx, &script, 0x01, SigVersion::Base).unwrap();
+        let mut sig = secp.sign_ecdsa(&msg, &sk).serialize_der().to_vec();
+        sig.push(0x01);
+
+        let mut interpreter = Interpreter::new(script).with_tx_context(&ctx);
+        assert!(interpreter.execute_script_with_stack(Stack::from(vec![sig])).unwrap());
+        assert_eq!(interpreter.sighashes_computed(), 1);
+    }
+
+    #[test]
+    fn tapscript_checksigadd_not_limited_by_op_count() {
+        let n = MAX_OPS_PER_SCRIPT + 50;
+        let script = (0..n)
+            .fold(Script::builder().push_int(0).unwrap(), |b, _| b.push_slice([0xab; 32]).push_opcode(OP_CHECKSIGADD))
+            .push_int(0).unwrap()
+            .push_opcode(OP_NUMEQUAL)
+            .into_script();
+        let run = |sig: Vec<u8>| {
+            let mut interpreter = Interpreter::new(script.clone())
+                .with_sig_version(SigVersion::Tapscript)
+                .with_validation_budget(0)
+                .with_stack(Stack::from(vec![sig; n]));
+            interpreter.execute_script()
+        };
+
+        // Empty signatures do not use any of the budget.
+        assert!(run(vec![]).unwrap());
+        let err = run(vec![0xab; 64]).unwrap_err();
+        assert_eq!(err, ScriptError::TapscriptValidationWeight);
+    }
+
+    #[test]
+    fn tapscript_not_limited_by_script_size() {
+        // One byte over the limit, leaving a single true item.
+        let script = (0..MAX_SCRIPT_SIZE / 2)
+            .fold(Script::builder().push_int(1).unwrap(), |b, _| b.push_int(1).unwrap().push_opcode(OP_DROP))
+            .into_script();
+        assert_eq!(script.len(), MAX_SCRIPT_SIZE + 1);
+
+        let err = Interpreter::new(script.clone()).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::ScriptSize);
+        let mut interpreter = Interpreter::new(script.clone()).with_sig_version(SigVersion::Tapscript);
+        assert!(interpreter.execute_script().unwrap());
+        let mut interpreter = Interpreter::new(script).with_sig_version(SigVersion::Tapscript);
+        assert_eq!(interpreter.trace().unwrap().len(), MAX_SCRIPT_SIZE + 1);
+    }
+
+    #[test]
+    fn tapscript_disables_checkmultisig() {
+        let script = Script::builder()
+            .push_int(0).unwrap()
+            .push_int(0).unwrap()
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        assert!(Interpreter::new(script.clone()).execute_script().unwrap());
+
+        let err = Interpreter::new(script).with_sig_version(SigVersion::Tapscript).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::TapscriptCheckMultisig);
+    }
+
+    #[test]
+    fn tapscript_pubkey_types() {
+        let run = |pubkey: &[u8], flags| {
+            let script = Script::builder()
+                .push_slice(PushBytesBuf::try_from(pubkey.to_vec()).unwrap())
+                .push_opcode(OP_CHECKSIG)
+                .into_script();
+            let mut interpreter = Interpreter::new(script)
+                .with_flags(flags)
+                .with_sig_version(SigVersion::Tapscript)
+                .with_validation_budget(1000)
+                .with_stack(Stack::from(vec![vec![0xab; 64]]));
+            interpreter.execute_script()
+        };
+        let flags = VerificationFlags::CONSENSUS;
+
+        assert_eq!(run(&[], flags), Err(ScriptError::PubkeyType));
+        // Unknown public key types succeed without a signature check.
+        assert_eq!(run(&[0x02; 33], flags), Ok(true));
+        let flags = flags.with(VerificationFlag::DiscourageUpgradablePubkeyType);
+        assert_eq!(run(&[0x02; 33], flags), Err(ScriptError::DiscourageUpgradablePubkeyType));
+    }
+
+    #[test]
+    fn signature_check_requires_tx_context() {
+        let script = Script::builder()
+            .push_slice([0xab; 9])
+            .push_slice([0x02; 33])
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::MissingTxContext);
+    }
+
+    #[test]
+    fn lock_time_requires_tx_context() {
+        let script = Script::builder().push_int(0).unwrap().push_opcode(OP_CLTV).into_script();
+
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::MissingTxContext);
+    }
+
+    #[test]
+    fn multisig_keys_count_toward_op_limit() {
+        // Each 0-of-20 multisig counts as 21 opcodes and the drop as one more.
+        let script = |multisigs| {
+            (0..multisigs)
+                .fold(Script::builder(), |b, _| {
+                    let b = (0..20).fold(b.push_int(0).unwrap().push_int(0).unwrap(), |b, _| b.push_slice([0x02; 33]));
+                    b.push_int(20).unwrap().push_opcode(OP_CHECKMULTISIG).push_opcode(OP_DROP)
+                })
+                .push_int(1).unwrap()
+                .into_script()
+        };
+
+        // The validation budget does not apply outside tapscript.
+        let mut interpreter = Interpreter::new(script(9)).with_validation_budget(0);
+        assert!(interpreter.execute_script().unwrap());
+
+        let err = Interpreter::new(script(10)).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::OpCount);
+    }
+
+    /// Returns a tapscript that checks the signature on top of the stack `n` times.
+    fn repeated_checksig(pubkey: &XOnlyPublicKey, n: usize) -> ScriptBuf {
+        let builder = (1..n).fold(Script::builder(), |b, _| {
+            b.push_opcode(OP_DUP).push_slice(pubkey.serialize()).push_opcode(OP_CHECKSIGVERIFY)
+        });
+        builder.push_slice(pubkey.serialize()).push_opcode(OP_CHECKSIG).into_script()
+    }
+
+    #[test]
+    fn validation_budget_limits_signature_checks() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[0x01; 32]).unwrap();
+        let (pubkey, _) = keypair.x_only_public_key();
+        let prevouts = vec![TxOut { value: Amount::ONE_BTC, script_pubkey: ScriptBuf::new() }];
+
+        // The witness `[<64 byte sig>]` serializes to 66 bytes so the budget
+        // of 116 permits exactly 2 signature checks.
+        let run = |n| {
+            let script = repeated_checksig(&pubkey, n);
+            let mut tx = spending_tx(0, Sequence::MAX);
+            let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+            let sighash = SighashCache::new(&tx)
+                .taproot_signature_hash(0, &Prevouts::All(&prevouts), None, Some((leaf_hash, u32::MAX)), TapSighashType::Default)
+                .unwrap();
+            let sig = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), &keypair).serialize();
+            tx.input[0].witness.push(sig);
+            assert_eq!(validation_budget(&tx.input[0].witness), 116);
+
+            let ctx = TxContext::new(tx, 0).unwrap().with_prevouts(prevouts.clone()).unwrap();
+            let mut stack = Stack::new();
+            stack.push(&sig);
+            let mut interpreter = Interpreter::new(script).with_sig_version(SigVersion::Tapscript).with_tx_context(&ctx);
+            interpreter.execute_script_with_stack(stack)
+        };
+
+        assert!(run(2).unwrap());
+        let err = run(3).unwrap_err();
+        assert_eq!(err, ScriptError::TapscriptValidationWeight);
+    }
+
+    #[test]
+    fn check_sig_add_oversized_accumulator() {
+        let script = Script::builder()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_slice(&[0x00, 0x00, 0x00, 0x80, 0x00])
+            .push_slice(&[0x02; 32])
+            .push_opcode(OP_CHECKSIGADD)
+            .into_script();
+        let mut interpreter = Interpreter::new(script).with_sig_version(SigVersion::Tapscript);
+
+        let err = interpreter.execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::InvalidScriptInt);
+    }
+
+    #[test]
+    fn hash256_max_size_element() {
+        let data = vec![0xab; MAX_SCRIPT_ELEMENT_SIZE];
+        let digest = bitcoin::hashes::sha256d::Hash::hash(&data).to_byte_array();
+        let script = Script::builder()
+            .push_slice(PushBytesBuf::try_from(data).unwrap())
+            .push_opcode(OP_HASH256)
+            .push_slice(digest)
+            .push_opcode(OP_EQUAL)
+            .into_script();
+
+        assert!(Interpreter::new(script).script_is_valid());
+    }
+
+    #[test]
+    fn steps_yields_each_instruction() {
+        let script = Script::builder()
+            .push_int(2).unwrap()
+            .push_int(3).unwrap()
+            .push_opcode(OP_ADD)
+            .into_script();
+        let mut interpreter = Interpreter::new(script);
+
+        let steps = interpreter.steps().collect::<Result<Vec<_>, _>>().unwrap();
+        let steps = steps.into_iter().map(|step| (step.opcode, step.stack)).collect::<Vec<_>>();
+        let want = vec![
+            (OP_PUSHNUM_2, vec![vec![0x02]]),
+            (OP_PUSHNUM_3, vec![vec![0x02], vec![0x03]]),
+            (OP_ADD, vec![vec![0x05]]),
+        ];
+        assert_eq!(steps, want);
+    }
+
+    /// Pushes the size of the top stack item (`OP_SIZE`).
+    struct Size;
+
+    impl OpcodeHandler for Size {
+        fn execute(&self, _: Opcode, stack: &mut Stack) -> Result<(), ScriptError> {
+            let len = stack.top().ok_or(ScriptError::InvalidStackOperation)?.len();
+            stack.push_num(len as i64);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn custom_opcode_handler() {
+        let script = Script::builder()
+            .push_slice([0xab; 3])
+            .push_opcode(OP_SIZE)
+            .push_int(3).unwrap()
+            .push_opcode(OP_EQUAL)
+            .into_script();
+        assert!(!is_supported(OP_SIZE));
+
+        assert!(Interpreter::new(script).with_opcode_handler(OP_SIZE, Size).script_is_valid());
+    }
+
+    /// Concatenates the top two stack items (`OP_CAT`).
+    struct Cat;
+
+    impl OpcodeHandler for Cat {
+        fn execute(&self, _: Opcode, stack: &mut Stack) -> Result<(), ScriptError> {
+            let b = stack.pop_bytes()?;
+            let mut a = stack.pop_bytes()?;
+            a.extend(b);
+            stack.push(&a);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn opcode_handler_limited_by_element_size() {
+        let half = PushBytesBuf::try_from(vec![0xab; MAX_SCRIPT_ELEMENT_SIZE / 2]).unwrap();
+        let script = Script::builder()
+            .push_slice(&half)
+            .push_slice(&half)
+            .push_opcode(OP_CAT)
+            .push_slice([0xab])
+            .push_opcode(OP_CAT)
+            .into_script();
+        let err = Interpreter::new(script).with_opcode_handler(OP_CAT, Cat).execute_script().unwrap_err();
+
+        assert_eq!(err, ScriptError::PushSize);
+    }
+
+    #[test]
+    fn restore_saved_state() {
+        let script = Script::builder()
+            .push_int(2).unwrap()
+            .push_opcode(OP_TOALTSTACK)
+            .push_int(1).unwrap()
+            .push_opcode(OP_IF)
+            .push_int(3).unwrap()
+            .push_opcode(OP_ELSE)
+            .push_int(4).unwrap()
+            .push_opcode(OP_ENDIF)
+            .push_opcode(OP_FROMALTSTACK)
+            .push_opcode(OP_ADD)
+            .push_int(5).unwrap()
+            .push_opcode(OP_NUMEQUAL)
+            .into_script();
+        let mut uninterrupted = Interpreter::new(script.clone());
+        let want = uninterrupted.execute_script().unwrap();
+
+        // Pause inside the `OP_IF` with an item on the alt stack.
+        let mut paused = Interpreter::new(script.clone());
+        assert_eq!(paused.steps().take(4).count(), 4);
+        let state = paused.save_state();
+
+        let mut resumed = Interpreter::new(script);
+        resumed.restore_state(state);
+
+        assert_eq!(resumed.execute_script().unwrap(), want);
+        assert_eq!(resumed.into_stack().items(), uninterrupted.into_stack().items());
+    }
+
+    #[test]
+    fn execute_same_interpreter_twice() {
+        let script = Script::builder()
+            .push_opcode(OP_TOALTSTACK)
+            .push_opcode(OP_FROMALTSTACK)
+            .push_int(1).unwrap()
+            .push_opcode(OP_EQUAL)
+            .into_script();
+        let mut interpreter = Interpreter::new(script);
+
+        assert!(interpreter.execute_script_with_stack(Stack::from(vec![vec![0x01]])).unwrap());
+        assert!(!interpreter.execute_script_with_stack(Stack::from(vec![vec![0x02]])).unwrap());
+        assert_eq!(interpreter.into_stack().items(), &[Vec::<u8>::new()]);
+    }
+
+    #[test]
+    fn rerun_resets_execution_state() {
+        let secp = Secp256k1::new();
+        let sk = bitcoin::secp256k1::SecretKey::from_slice(&[0x01; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&secp, &sk);
+        let script = Script::builder()
+            .push_opcode(OP_DUP)
+            .push_slice(pubkey.serialize())
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_slice(pubkey.serialize())
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let ctx = TxContext::new(spending_tx(0, Sequence::MAX), 0).unwrap();
+        let msg = build_sighash(&ctx, &script, 0x01, SigVersion::Base).unwrap();
+        let mut sig = secp.sign_ecdsa(&msg, &sk).serialize_der().to_vec();
+        sig.push(0x01);
+        let mut interpreter = Interpreter::new(script).with_tx_context(&ctx);
+
+        for _ in 0..2 {
+            assert!(interpreter.execute_script_with_stack(Stack::from(vec![sig.clone()])).unwrap());
+            assert_eq!(interpreter.sigops(), 2);
+            assert_eq!(interpreter.sighashes_computed(), 1);
+        }
+        // Stepping after a completed execution starts over.
+        interpreter = interpreter.with_stack(Stack::from(vec![sig]));
+        assert_eq!(interpreter.trace().unwrap().len(), 5);
+        assert_eq!(interpreter.sigops(), 2);
+
+        // The budget set by the builder is restored, an unknown public key
+        // type uses the budget without a signature check.
+        let script = Script::builder().push_slice([0x02; 33]).push_opcode(OP_CHECKSIG).into_script();
+        let mut interpreter = Interpreter::new(script)
+            .with_sig_version(SigVersion::Tapscript)
+            .with_validation_budget(VALIDATION_WEIGHT_PER_SIGOP_PASSED);
+        for _ in 0..2 {
+            assert!(interpreter.execute_script_with_stack(Stack::from(vec![vec![0xab; 64]])).unwrap());
+        }
+    }
+
+    #[test]
+    fn from_alt_stack_empty() {
+        let script = Script::builder()
+            .push_int(1).unwrap()
+            .push_opcode(OP_FROMALTSTACK)
+            .into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+
+        assert_eq!(err, ScriptError::InvalidAltstackOperation);
+    }
+
+    #[test]
+    fn step_through_script() {
+        let script = Script::builder()
+            .push_int(5).unwrap()
+            .push_int(2).unwrap()
+            .push_int(3).unwrap()
+            .push_opcode(OP_ADD)
+            .push_opcode(OP_EQUAL)
+            .into_script();
+        let mut interpreter = Interpreter::new(script);
+
+        let mut step = || interpreter.step().unwrap().map(|step| (step.pc, step.opcode, step.stack));
+        assert_eq!(step(), Some((0, OP_PUSHNUM_5, vec![vec![0x05]])));
+        assert_eq!(step(), Some((1, OP_PUSHNUM_2, vec![vec![0x05], vec![0x02]])));
+        assert_eq!(step(), Some((2, OP_PUSHNUM_3, vec![vec![0x05], vec![0x02], vec![0x03]])));
+        assert_eq!(step(), Some((3, OP_ADD, vec![vec![0x05], vec![0x05]])));
+        assert_eq!(step(), Some((4, OP_EQUAL, vec![vec![0x01]])));
+        assert_eq!(step(), None);
+    }
+
+    #[test]
+    fn trace_to_completion() {
+        let script = Script::builder().push_int(2).unwrap().push_int(3).unwrap().push_opcode(OP_ADD).into_script();
+        let steps = Interpreter::new(script).trace().unwrap();
+
+        let want = vec![
+            StepInfo { pc: 0, opcode: OP_PUSHNUM_2, stack: vec![vec![0x02]], condition_stack: vec![] },
+            StepInfo { pc: 1, opcode: OP_PUSHNUM_3, stack: vec![vec![0x02], vec![0x03]], condition_stack: vec![] },
+            StepInfo { pc: 2, opcode: OP_ADD, stack: vec![vec![0x05]], condition_stack: vec![] },
+        ];
+        assert_eq!(steps, want);
+    }
+
+    /// Executes `1 2 <n> <op>` returning the interpreter.
+    fn index_op(n: i64, op: Opcode) -> Interpreter<'static> {
+        let script = Script::builder()
+            .push_int(1).unwrap()
+            .push_int(2).unwrap()
+            .push_int(n).unwrap()
+            .push_opcode(op)
+            .into_script();
+        Interpreter::new(script)
+    }
+
+    #[test]
+    fn pick_zero_duplicates_top() {
+        let mut interpreter = index_op(0, OP_PICK);
+        interpreter.execute_script().unwrap();
+
+        assert_eq!(interpreter.stack.items(), &[vec![0x01], vec![0x02], vec![0x02]]);
+    }
+
+    #[test]
+    fn roll_zero_is_noop() {
+        let mut interpreter = index_op(0, OP_ROLL);
+        interpreter.execute_script().unwrap();
+
+        assert_eq!(interpreter.stack.items(), &[vec![0x01], vec![0x02]]);
+    }
+
+    #[test]
+    fn roll_moves_item_to_top() {
+        let mut interpreter = index_op(1, OP_ROLL);
+        interpreter.execute_script().unwrap();
+
+        assert_eq!(interpreter.stack.items(), &[vec![0x02], vec![0x01]]);
+    }
+
+    #[test]
+    fn negative_stack_index_fails() {
+        for op in [OP_PICK, OP_ROLL] {
+            let err = index_op(-1, op).execute_script().unwrap_err();
+            assert_eq!(err, ScriptError::InvalidStackOperation);
+        }
+    }
+
+    #[test]
+    fn out_of_range_stack_index_fails() {
+        for op in [OP_PICK, OP_ROLL] {
+            let err = index_op(2, op).execute_script().unwrap_err();
+            assert_eq!(err, ScriptError::InvalidStackOperation);
+
+            let script = Script::builder().push_opcode(op).into_script();
+            let err = Interpreter::new(script).execute_script().unwrap_err();
+            assert_eq!(err, ScriptError::StackUnderflow { opcode: op });
+        }
+    }
+
+    /// The unsigned transaction from the BIP-143 native P2WPKH example.
+    const BIP143_TX: &str = "0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000";
+
+    /// Returns the message for `digest` given as hex.
+    fn message(digest: &str) -> Message {
+        Message::from_digest(hex_to_bytes(digest).try_into().unwrap())
+    }
+
+    #[test]
+    fn build_legacy_sighash() {
+        let tx: Transaction = bitcoin::consensus::deserialize(&hex_to_bytes(BIP143_TX)).unwrap();
+        let ctx = TxContext::new(tx, 0).unwrap();
+        // The P2PK script pubkey spent by input 0.
+        let script_code = ScriptBuf::from_bytes(hex_to_bytes(
+            "2103c9f4836b9a4f77fc0d81f7bcb01b7f1b35916864b9476c241ce9fc198bd25432ac",
+        ));
+
+        let msg = build_sighash(&ctx, &script_code, 0x01, SigVersion::Base).unwrap();
+        assert_eq!(msg, message("63cec688ee06a91e913875356dd4dea2f8e0f2a2659885372da2a37e32c7532e"));
+    }
+
+    #[test]
+    fn build_segwit_v0_sighash() {
+        let tx: Transaction = bitcoin::consensus::deserialize(&hex_to_bytes(BIP143_TX)).unwrap();
+        let prevouts = vec![
+            TxOut { value: Amount::from_sat(625_000_000), script_pubkey: ScriptBuf::new() },
+            TxOut {
+                value: Amount::from_sat(600_000_000),
+                script_pubkey: ScriptBuf::from_bytes(hex_to_bytes("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1")),
+            },
+        ];
+        let ctx = TxContext::new(tx, 1).unwrap().with_prevouts(prevouts).unwrap();
+        let script_code = ScriptBuf::from_bytes(hex_to_bytes("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac"));
+
+        let msg = build_sighash(&ctx, &script_code, 0x01, SigVersion::WitnessV0).unwrap();
+        assert_eq!(msg, message("c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"));
+    }
+
+    #[test]
+    fn pushnum_neg1_pushes_minimal_encoding() {
+        // Also known as `OP_1NEGATE`.
+        let script = Script::builder().push_opcode(OP_PUSHNUM_NEG1).into_script();
+        let mut interpreter = Interpreter::new(script);
+        interpreter.execute_script().unwrap();
+
+        assert_eq!(interpreter.stack.items(), &[vec![0x81]]);
+        assert_eq!(interpreter.stack.top_num().unwrap(), -1);
+    }
+
+    #[test]
+    fn execute_script_pubkey_with_stack() {
+        let script_pubkey = Script::builder()
+            .push_opcode(OP_ADD)
+            .push_int(5).unwrap()
+            .push_opcode(OP_EQUAL)
+            .into_script();
+        let mut stack = Stack::new();
+        stack.push_num(2);
+        stack.push_num(3);
+
+        let mut interpreter = Interpreter::new(script_pubkey);
+        assert!(interpreter.execute_script_with_stack(stack).unwrap());
+        assert_eq!(interpreter.stack.len(), 1);
+    }
+
+    #[test]
+    fn permissive_disabled_opcode_fails() {
+        let script = Script::builder().push_int(2).unwrap().push_int(3).unwrap().push_opcode(OP_MUL).into_script();
+        let err = Interpreter::new(script).with_flags(VerificationFlags::PERMISSIVE).execute_script().unwrap_err();
+
+        assert_eq!(err, ScriptError::DisabledOpcode);
+    }
+
+    #[test]
+    fn permissive_non_minimal_push() {
+        // Pushes 5 with OP_PUSHBYTES_1 instead of OP_PUSHNUM_5.
+        let script = Script::builder().push_slice(&[0x05]).into_script();
+
+        assert!(Interpreter::new(script.clone()).with_flags(VerificationFlags::PERMISSIVE).script_is_valid());
+
+        let flags = VerificationFlags::PERMISSIVE.with(VerificationFlag::MinimalData);
+        let err = Interpreter::new(script).with_flags(flags).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::MinimalData);
+    }
+
+    #[test]
+    fn op_count_limit() {
+        let script = |ops| (0..ops).fold(Script::builder().push_int(1).unwrap(), |b, _| b.push_opcode(OP_DUP)).into_script();
+
+        let mut interpreter = Interpreter::new(script(MAX_OPS_PER_SCRIPT)).with_flags(VerificationFlags::PERMISSIVE);
+        assert!(interpreter.execute_script().is_ok());
+
+        let mut interpreter = Interpreter::new(script(MAX_OPS_PER_SCRIPT + 1)).with_flags(VerificationFlags::PERMISSIVE);
+        let err = interpreter.execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::OpCount);
+    }
+
+    #[test]
+    fn report_equal_verify_failure() {
+        let script = Script::builder()
+            .push_int(1).unwrap()
+            .push_int(2).unwrap()
+            .push_int(3).unwrap()
+            .push_opcode(OP_EQUALVERIFY)
+            .into_script();
+        let report = Interpreter::new(script).execute_script_with_result().render_report();
+
+        let want = concat!(
+            "0: OP_PUSHNUM_1 [01]\n",
+            "1: OP_PUSHNUM_2 [01 02]\n",
+            "2: OP_PUSHNUM_3 [01 02 03]\n",
+            "final stack: [01]\n",
+            "error at 3 (OP_EQUALVERIFY): script failed an OP_EQUALVERIFY operation",
+        );
+        assert_eq!(report, want);
+    }
+
+    #[test]
+    fn multisig_non_minimal_key_count() {
+        // A 0-of-1 multisig with the key count 1 padded with a zero byte.
+        let script = Script::builder()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_slice([0x02; 33])
+            .push_slice(&[0x01, 0x00])
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+
+        assert!(Interpreter::new(script.clone()).script_is_valid());
+
+        let flags = VerificationFlags::CONSENSUS.with(VerificationFlag::MinimalData);
+        assert!(Interpreter::new(script).with_flags(flags).execute_script().is_err());
+    }
+
+    #[test]
+    fn drops_count_toward_op_limit() {
+        let script = |drops| {
+            let pushed = (0..=drops).fold(Script::builder(), |b, _| b.push_opcode(OP_PUSHNUM_1));
+            (0..drops).fold(pushed, |b, _| b.push_opcode(OP_DROP)).into_script()
+        };
+
+        assert!(Interpreter::new(script(MAX_OPS_PER_SCRIPT)).script_is_valid());
+
+        let err = Interpreter::new(script(MAX_OPS_PER_SCRIPT + 1)).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::OpCount);
+    }
+
+    #[test]
+    fn nip_and_2drop() {
+        let script = Script::builder()
+            .push_int(1).unwrap()
+            .push_int(2).unwrap()
+            .push_int(3).unwrap()
+            .push_int(4).unwrap()
+            .push_opcode(OP_NIP)
+            .push_opcode(OP_2DROP)
+            .into_script();
+        let mut interpreter = Interpreter::new(script);
+        interpreter.execute_script().unwrap();
+
+        assert_eq!(interpreter.stack.items(), &[vec![0x01]]);
+    }
+
+    /// Executes `1 2 3 <op>` returning the final stack.
+    fn stack_op(op: Opcode) -> Vec<Vec<u8>> {
+        let script = Script::builder()
+            .push_int(1).unwrap()
+            .push_int(2).unwrap()
+            .push_int(3).unwrap()
+            .push_opcode(op)
+            .into_script();
+        let mut interpreter = Interpreter::new(script);
+        interpreter.execute_script().unwrap();
+        interpreter.into_stack().items().to_vec()
+    }
+
+    #[test]
+    fn stack_ops() {
+        let (one, two, three) = (vec![0x01], vec![0x02], vec![0x03]);
+
+        assert_eq!(stack_op(OP_DUP), vec![one.clone(), two.clone(), three.clone(), three.clone()]);
+        assert_eq!(stack_op(OP_DROP), vec![one.clone(), two.clone()]);
+        assert_eq!(stack_op(OP_SWAP), vec![one.clone(), three.clone(), two.clone()]);
+        assert_eq!(stack_op(OP_OVER), vec![one.clone(), two.clone(), three.clone(), two.clone()]);
+        assert_eq!(stack_op(OP_2DUP), vec![one.clone(), two.clone(), three.clone(), two.clone(), three.clone()]);
+        assert_eq!(stack_op(OP_ROT), vec![two.clone(), three.clone(), one.clone()]);
+        assert_eq!(stack_op(OP_NIP), vec![one.clone(), three.clone()]);
+        assert_eq!(stack_op(OP_TUCK), vec![one, three.clone(), two, three]);
+    }
+
+    #[test]
+    fn stack_ops_on_empty_stack_fail() {
+        for op in [OP_DUP, OP_DROP, OP_SWAP, OP_OVER, OP_2DUP, OP_ROT, OP_NIP, OP_TUCK, OP_PICK, OP_ROLL] {
+            let script = Script::builder().push_opcode(op).into_script();
+            let err = Interpreter::new(script).execute_script().unwrap_err();
+
+            assert_eq!(err, ScriptError::StackUnderflow { opcode: op });
+        }
+    }
+
+    #[test]
+    fn raised_max_element_size() {
+        let script = Script::builder().push_slice(PushBytesBuf::try_from(vec![0xab; 1000]).unwrap()).into_script();
+
+        let err = Interpreter::new(script.clone()).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::PushSize);
+        assert!(Interpreter::new(script).with_max_element_size(1000).script_is_valid());
+    }
+
+    #[test]
+    fn nested_condition_stack() {
+        let script = Script::builder()
+            .push_int(1).unwrap()
+            .push_opcode(OP_IF)
+            .push_int(0).unwrap()
+            .push_opcode(OP_IF)
+            .push_int(2).unwrap()
+            .push_opcode(OP_ELSE)
+            .push_int(3).unwrap()
+            .push_opcode(OP_ENDIF)
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        let mut interpreter = Interpreter::new(script);
+
+        let mut conditions = Vec::new();
+        let res = interpreter.execute_script_with_trace(|step| conditions.push(step.condition_stack.clone()));
+        assert!(res.unwrap());
+        assert_eq!(interpreter.stack.items(), &[vec![0x03]]);
+
+        let want: Vec<Vec<bool>> = vec![
+            vec![],
+            vec![true],
+            vec![true],
+            vec![true, false],
+            vec![true, false],
+            vec![true, true],
+            vec![true, true],
+            vec![true],
+            vec![],
+        ];
+        assert_eq!(conditions, want);
+    }
+
+    #[test]
+    fn unbalanced_conditional() {
+        let script = Script::builder().push_int(1).unwrap().push_opcode(OP_IF).into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::UnbalancedConditional);
+
+        let script = Script::builder().push_int(1).unwrap().push_opcode(OP_ENDIF).into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::UnbalancedConditional);
+    }
+
+    /// Executes `<condition> <op> 2 OP_ELSE 3 OP_ENDIF` returning the final stack.
+    fn conditional(condition: i64, op: Opcode) -> Vec<Vec<u8>> {
+        let script = Script::builder()
+            .push_int(condition).unwrap()
+            .push_opcode(op)
+            .push_int(2).unwrap()
+            .push_opcode(OP_ELSE)
+            .push_int(3).unwrap()
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        let mut interpreter = Interpreter::new(script);
+        interpreter.execute_script().unwrap();
+        interpreter.into_stack().items().to_vec()
+    }
+
+    #[test]
+    fn if_else_branches() {
+        assert_eq!(conditional(1, OP_IF), vec![vec![0x02]]);
+        assert_eq!(conditional(0, OP_IF), vec![vec![0x03]]);
+        assert_eq!(conditional(1, OP_NOTIF), vec![vec![0x03]]);
+        assert_eq!(conditional(0, OP_NOTIF), vec![vec![0x02]]);
+    }
+
+    #[test]
+    fn skipped_branch_is_not_executed() {
+        let script = Script::builder()
+            .push_int(0).unwrap()
+            .push_opcode(OP_IF)
+            .push_opcode(OP_RETURN)
+            .push_opcode(OP_ENDIF)
+            .push_int(1).unwrap()
+            .into_script();
+
+        assert!(Interpreter::new(script).script_is_valid());
+    }
+
+    #[test]
+    fn if_on_empty_stack_fails() {
+        let script = Script::builder().push_opcode(OP_IF).push_opcode(OP_ENDIF).into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+
+        assert_eq!(err, ScriptError::StackUnderflow { opcode: OP_IF });
+    }
+
+    #[test]
+    fn unmatched_if_position() {
+        let script = Script::builder()
+            .push_opcode(OP_PUSHNUM_1)
+            .push_opcode(OP_IF)
+            .push_opcode(OP_PUSHNUM_2)
+            .into_script();
+        let res = Interpreter::new(script).execute_script_with_result();
+
+        let err = res.result.unwrap_err();
+        assert_eq!(err, ScriptError::UnbalancedConditional);
+        assert_eq!(res.failed_at, Some((1, OP_IF)));
+    }
+
+    #[test]
+    fn stack_underflow_reports_opcode() {
+        let script = Script::builder().push_opcode(OP_ADD).into_script();
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+
+        assert_eq!(err, ScriptError::StackUnderflow { opcode: OP_ADD });
+        assert!(err.to_string().contains("OP_ADD"));
+    }
+
+    #[test]
+    fn push_exceeds_max_element_size() {
+        let data = vec![0xab; MAX_SCRIPT_ELEMENT_SIZE + 1];
+        let script = Script::builder()
+            .push_slice(PushBytesBuf::try_from(data).unwrap())
+            .push_opcode(OP_HASH256)
+            .into_script();
+
+        let err = Interpreter::new(script).execute_script().unwrap_err();
+        assert_eq!(err, ScriptError::PushSize);
+    }
+}
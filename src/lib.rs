@@ -2,15 +2,39 @@
 
 //! # Bitcoin Script Interpreter
 
+pub mod analysis;
+pub mod context;
+pub mod error;
+pub mod flags;
+pub mod hash;
 pub mod interpreter;
-mod stack;
+pub mod stack;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 
-use anyhow::Result;
-use bitcoin::ScriptBuf;
+use anyhow::{anyhow, bail, Result};
+use bitcoin::opcodes::all::{OP_PUSHBYTES_0, OP_PUSHNUM_1};
+use bitcoin::opcodes::Opcode;
+use bitcoin::script::{Instruction, ScriptExt};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Psbt, Script, ScriptBuf, Transaction, TxOut, Witness};
 
-use crate::interpreter::Interpreter;
+use crate::analysis::is_p2sh;
+use crate::context::TxContext;
+use crate::error::ScriptError;
+use crate::flags::{VerificationFlag, VerificationFlags};
+use crate::hash::{DefaultBackend, HashBackend};
+use crate::interpreter::{
+    is_op_success, push_encoding, verify_taproot_key_path, ExecutionResult, Interpreter, SigVersion, StepInfo,
+    MAX_SCRIPT_ELEMENT_SIZE, MAX_STACK_SIZE,
+};
+use crate::stack::Stack;
 
-/// Executes the script `script_sig |  script_pubkey`.
+/// The maximum size in bytes of a standard P2WSH witness script.
+const MAX_STANDARD_P2WSH_SCRIPT_SIZE: usize = 3600;
+
+/// Executes `script_sig` followed by `script_pubkey`, and the redeem
+/// script for P2SH spends.
 ///
 /// A Bitcoin script is valid if after execution the stack is non-empty and non-zero.
 ///
@@ -18,23 +42,1077 @@ use crate::interpreter::Interpreter;
 ///
 /// - `Ok(top_of_stack)` if script terminated successfully.
 /// - `Err()` if something in the script triggered failure.
-pub fn execute(script_sig: ScriptBuf, script_pubkey: ScriptBuf) -> Result<bool> {
-    let script = join_parts(script_sig, script_pubkey);
-    let mut interpreter = Interpreter::new(script);
-    interpreter.execute_script()
+pub fn execute(script_sig: ScriptBuf, script_pubkey: ScriptBuf) -> Result<bool, ScriptError> {
+    verify_script(&script_sig, &script_pubkey, VerificationFlags::CONSENSUS, None)
 }
 
-/// Checks if the script `script_sig |  script_pubkey` is valid.
+/// Checks if the script sig and script pubkey are valid.
 ///
 /// A Bitcoin script is valid if after execution the stack is non-empty and non-zero.
 pub fn is_valid(script_sig: ScriptBuf, script_pubkey: ScriptBuf) -> bool {
-    let script = join_parts(script_sig, script_pubkey);
-    let mut interpreter = Interpreter::new(script);
-    interpreter.script_is_valid()
+    execute(script_sig, script_pubkey).unwrap_or(false)
+}
+
+/// Executes the script fragment `script` starting with `initial_stack`.
+///
+/// Useful to evaluate a fragment, e.g. a redeem script, against the
+/// stack left by some other script.
+///
+/// # Returns
+///
+/// - `Ok(top_of_stack)` if script terminated successfully.
+/// - `Err()` if something in the script triggered failure.
+pub fn eval_truthy(script: &Script, initial_stack: Stack) -> Result<bool, ScriptError> {
+    let stack = run_script(script, initial_stack, VerificationFlags::CONSENSUS, None, SigVersion::Base, &mut 0, &mut |_| {})?;
+    Ok(stack.is_true())
+}
+
+/// Returns the P2WSH script pubkey `OP_0 <sha256(witness_script)>`.
+///
+/// This is the witness program a spend revealing `witness_script` is
+/// checked against.
+pub fn p2wsh_script_pubkey(witness_script: &Script) -> ScriptBuf {
+    Script::builder()
+        .push_opcode(OP_PUSHBYTES_0)
+        .push_slice(DefaultBackend::sha256(witness_script.as_bytes()))
+        .into_script()
+}
+
+/// Returns the P2WPKH script pubkey `OP_0 <hash160(pubkey)>`, the
+/// public key is serialized compressed.
+pub fn p2wpkh_script_pubkey(pubkey: &PublicKey) -> ScriptBuf {
+    Script::builder()
+        .push_opcode(OP_PUSHBYTES_0)
+        .push_slice(DefaultBackend::hash160(&pubkey.serialize()))
+        .into_script()
+}
+
+/// Executes the tapscript `leaf_script` starting with `witness_stack`.
+///
+/// Applies the tapscript rules (BIP-342) so that a leaf can be tested
+/// without building a full taproot spend, `budget` is the validation
+/// budget available for signature checks. `ctx` is the spending
+/// transaction, including the prevouts, used for signature checks.
+///
+/// # Returns
+///
+/// The result of execution, if `leaf_script` contains an `OP_SUCCESSx`
+/// opcode it succeeds without being executed. Returns an error if
+/// `leaf_script` fails to parse, the initial stack is too big, or if
+/// `OP_SUCCESSx` is discouraged by `flags`.
+pub fn execute_tapscript(
+    leaf_script: &Script,
+    witness_stack: Vec<Vec<u8>>,
+    budget: i64,
+    flags: VerificationFlags,
+    ctx: &TxContext,
+) -> Result<ExecutionResult, ScriptError> {
+    for ins in leaf_script.instructions() {
+        if let Instruction::Op(op) = ins.map_err(|_| ScriptError::BadOpcode)? {
+            if is_op_success(op) {
+                if flags.contains(VerificationFlag::DiscourageOpSuccess) {
+                    return Err(ScriptError::DiscourageOpSuccess);
+                }
+                return Ok(ExecutionResult { trace: vec![], stack: witness_stack, result: Ok(true), failed_at: None });
+            }
+        }
+    }
+    if witness_stack.len() > MAX_STACK_SIZE {
+        return Err(ScriptError::StackSize);
+    }
+    if witness_stack.iter().any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+        return Err(ScriptError::PushSize);
+    }
+
+    let mut interpreter = Interpreter::new(leaf_script.to_owned())
+        .with_flags(flags)
+        .with_sig_version(SigVersion::Tapscript)
+        .with_validation_budget(budget)
+        .with_tx_context(ctx)
+        .with_stack(Stack::from(witness_stack));
+    Ok(interpreter.execute_script_with_result())
+}
+
+/// Verifies `script_sig` and `script_pubkey`, as [`execute`] does, and
+/// returns a JSON trace of the execution.
+///
+/// The trace is an array with one object per executed instruction, of the
+/// script sig, the script pubkey, then the redeem script for P2SH spends.
+/// Each object contains the byte offset of the instruction within its
+/// script (`pc`), the opcode name (`opcode`), and the stack after
+/// execution as hex strings, top item last (`stack`). If the script fails
+/// the trace ends at the last successfully executed instruction.
+pub fn trace_to_json(script_sig: ScriptBuf, script_pubkey: ScriptBuf, flags: VerificationFlags) -> String {
+    let mut steps = Vec::new();
+    let _ = verify_script_counting_sigops(&script_sig, &script_pubkey, flags, None, &mut 0, &mut |step| {
+        let stack = step.stack.iter().map(|item| format!("\"{}\"", to_hex(item))).collect::<Vec<_>>();
+        steps.push(format!("{{\"pc\":{},\"opcode\":\"{}\",\"stack\":[{}]}}", step.pc, step.opcode, stack.join(",")));
+    });
+    format!("[{}]", steps.join(","))
+}
+
+/// Encodes `bytes` as a lower case hex string.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Verifies the finalized input `input_index` of `psbt`.
+///
+/// Executes the finalized script sig and witness against the script
+/// pubkey of the output being spent. Useful to sanity check a PSBT before
+/// extracting the transaction.
+///
+/// The outputs spent by every input must be known, segwit v0 and taproot
+/// signatures commit to them.
+///
+/// # Returns
+///
+/// - `Ok(top_of_stack)` if script terminated successfully.
+/// - `Err()` if the input is not finalized, the output spent by any input
+///   is not known, or something in the script triggered failure.
+pub fn verify_psbt_input(psbt: &Psbt, input_index: usize) -> Result<bool> {
+    let input = psbt.inputs.get(input_index).ok_or_else(|| anyhow!("input index {} out of range", input_index))?;
+    if input.final_script_sig.is_none() && input.final_script_witness.is_none() {
+        bail!("input {} is not finalized", input_index);
+    }
+    let script_sig = input.final_script_sig.clone().unwrap_or_default();
+    let witness = input.final_script_witness.clone().unwrap_or_default();
+    let prevouts = (0..psbt.inputs.len()).map(|i| psbt_spent_output(psbt, i)).collect::<Result<Vec<_>>>()?;
+    let script_pubkey = prevouts[input_index].script_pubkey.clone();
+
+    let mut tx = psbt.unsigned_tx.clone();
+    tx.input[input_index].script_sig = script_sig.clone();
+    tx.input[input_index].witness = witness;
+    let ctx = TxContext::new(tx, input_index)?.with_prevouts(prevouts)?;
+
+    Ok(verify_script(&script_sig, &script_pubkey, VerificationFlags::CONSENSUS, Some(&ctx))?)
+}
+
+/// Verifies input `input_index` of `tx` under the consensus rules active
+/// on mainnet at block `height`.
+///
+/// `prevouts` are the outputs spent by each input of `tx`. Useful to
+/// replay historical transactions.
+///
+/// # Returns
+///
+/// - `Ok(top_of_stack)` if script terminated successfully.
+/// - `Err()` if `input_index` is out of range, there is not one prevout
+///   per input, or something in the script triggered failure.
+pub fn verify_input_at_height(tx: &Transaction, prevouts: &[TxOut], input_index: usize, height: u32) -> Result<bool> {
+    let ctx = TxContext::new(tx.clone(), input_index)?.with_prevouts(prevouts.to_vec())?;
+    let flags = VerificationFlags::mandatory_at_height(height);
+    Ok(verify_script(&ctx.input().script_sig, &prevouts[input_index].script_pubkey, flags, Some(&ctx))?)
 }
 
-/// Joins the script sig and script pubkey together into a single
-/// script ready for execution.
-fn join_parts(script_sig: ScriptBuf, script_pubkey: ScriptBuf) -> ScriptBuf {
-    ScriptBuf::from_bytes(script_sig.into_bytes().into_iter().chain(script_pubkey.into_bytes()).collect())
+/// Verifies every input of `tx` under `flags`.
+///
+/// `prevouts` are the outputs spent by each input of `tx`.
+///
+/// # Returns
+///
+/// - `Ok(true)` if every input is valid.
+/// - `Ok(false)` if the script of an input evaluates to false.
+/// - `Err()` if there is not one prevout per input, or something in the
+///   script of an input triggered failure.
+pub fn verify_transaction(tx: &Transaction, prevouts: &[TxOut], flags: VerificationFlags) -> Result<bool> {
+    for input_index in 0..tx.input.len() {
+        let ctx = TxContext::new(tx.clone(), input_index)?.with_prevouts(prevouts.to_vec())?;
+        let script_pubkey = &prevouts[input_index].script_pubkey;
+        if !verify_script(&ctx.input().script_sig, script_pubkey, flags, Some(&ctx))? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Returns the output spent by input `input_index` of `psbt`.
+fn psbt_spent_output(psbt: &Psbt, input_index: usize) -> Result<TxOut> {
+    let input = &psbt.inputs[input_index];
+    if let Some(ref utxo) = input.witness_utxo {
+        return Ok(utxo.clone());
+    }
+    if let Some(ref prev_tx) = input.non_witness_utxo {
+        let outpoint = psbt.unsigned_tx.input[input_index].previous_output;
+        if prev_tx.compute_txid() != outpoint.txid {
+            bail!("non-witness utxo for input {} does not match the spent outpoint", input_index);
+        }
+        return prev_tx
+            .output
+            .get(outpoint.vout as usize)
+            .cloned()
+            .ok_or_else(|| anyhow!("non-witness utxo for input {} is missing output {}", input_index, outpoint.vout));
+    }
+    bail!("input {} is missing the spent output", input_index)
+}
+
+/// Verifies the input `ctx` spending an output locked with `script_pubkey`.
+///
+/// # Returns
+///
+/// - `Ok(sigops)` the number of signature operations executed, if the
+///   input is valid.
+/// - `Err()` if the script evaluated to false or something in the
+///   script triggered failure.
+pub fn verify_input_with_sigops(
+    script_pubkey: &Script,
+    flags: VerificationFlags,
+    ctx: &TxContext,
+) -> Result<usize> {
+    let mut sigops = 0;
+    if !verify_script_counting_sigops(&ctx.input().script_sig, script_pubkey, flags, Some(ctx), &mut sigops, &mut |_| {})? {
+        bail!(ScriptError::EvalFalse);
+    }
+    Ok(sigops)
+}
+
+/// Reports the verification flags that change whether `script_sig` and
+/// `script_pubkey` are valid.
+///
+/// Each flag is toggled on its own, starting from the consensus flags,
+/// this tells which policy rule a transaction violates. `ctx` is the
+/// spending transaction, required if the scripts check signatures.
+///
+/// Only the [`VerificationFlag::ENFORCED`] flags are toggled, the others
+/// never change the result so are never reported.
+///
+/// # Returns
+///
+/// The flags that change the result, with the validity when toggled.
+/// Returns an error if `ctx` is missing or does not include the prevouts
+/// but the scripts require them.
+pub fn flag_sensitivity(
+    script_sig: &Script,
+    script_pubkey: &Script,
+    ctx: Option<&TxContext>,
+) -> Result<Vec<(VerificationFlag, bool)>, ScriptError> {
+    let base = VerificationFlags::CONSENSUS;
+    let is_valid = |flags| match verify_script(script_sig, script_pubkey, flags, ctx) {
+        Err(e @ (ScriptError::MissingTxContext | ScriptError::MissingPrevouts)) => Err(e),
+        res => Ok(res.unwrap_or(false)),
+    };
+    let want = is_valid(base)?;
+
+    let mut sensitive = Vec::new();
+    for flag in VerificationFlag::ENFORCED {
+        let flags = if base.contains(flag) { base.without(flag) } else { base.with(flag) };
+        let valid = is_valid(flags)?;
+        if valid != want {
+            sensitive.push((flag, valid));
+        }
+    }
+    Ok(sensitive)
+}
+
+/// Executes `script_sig` followed by `script_pubkey`, and the redeem
+/// script for P2SH spends, then the witness for segwit spends.
+fn verify_script(
+    script_sig: &Script,
+    script_pubkey: &Script,
+    flags: VerificationFlags,
+    ctx: Option<&TxContext>,
+) -> Result<bool, ScriptError> {
+    verify_script_counting_sigops(script_sig, script_pubkey, flags, ctx, &mut 0, &mut |_| {})
+}
+
+/// Same as [`verify_script`], adding the number of signature operations
+/// executed to `sigops` and calling `on_step` after each instruction of
+/// every script executed.
+///
+/// The stack is moved, not copied, from one script to the next.
+fn verify_script_counting_sigops(
+    script_sig: &Script,
+    script_pubkey: &Script,
+    flags: VerificationFlags,
+    ctx: Option<&TxContext>,
+    sigops: &mut usize,
+    on_step: &mut dyn FnMut(&StepInfo),
+) -> Result<bool, ScriptError> {
+    // Spending P2SH requires a push only script sig even if `SigPushOnly`
+    // is not set, this is checked before the redeem script is extracted.
+    let p2sh = flags.contains(VerificationFlag::P2sh) && is_p2sh(script_pubkey);
+    if (p2sh || flags.contains(VerificationFlag::SigPushOnly)) && !script_sig.is_push_only() {
+        return Err(ScriptError::SigPushOnly);
+    }
+
+    let stack = run_script(script_sig, Stack::new(), flags, ctx, SigVersion::Base, sigops, on_step)?;
+    let (stack, redeem_script) = run_script_pubkey(script_pubkey, stack, flags, ctx, sigops, on_step)?;
+    if !stack.is_true() {
+        return Ok(false);
+    }
+
+    let empty = Witness::new();
+    let witness = ctx.map_or(&empty, |ctx| &ctx.input().witness);
+    if flags.contains(VerificationFlag::Witness) {
+        let program = redeem_script.as_deref().unwrap_or(script_pubkey);
+        if program.is_witness_program() {
+            // The script sig must not be malleable, for native segwit it
+            // is empty and for P2SH wrapped segwit it only pushes the
+            // redeem script.
+            let want = redeem_script.as_ref().map(|s| push_encoding(s.as_bytes())).unwrap_or_default();
+            if script_sig.as_bytes() != want {
+                return Err(ScriptError::WitnessMalleated);
+            }
+            return verify_witness_program(program, redeem_script.is_some(), witness, flags, ctx, sigops, on_step);
+        }
+        if !witness.is_empty() {
+            return Err(ScriptError::WitnessUnexpected);
+        }
+    }
+    if flags.contains(VerificationFlag::CleanStack) {
+        stack.final_validity(true)?;
+    }
+    Ok(true)
+}
+
+/// Executes `script_pubkey`, and the redeem script for P2SH spends,
+/// against the stack left by the script sig.
+///
+/// # Returns
+///
+/// The stack after execution, this is the stack left by the script
+/// pubkey if it evaluates to false, and the redeem script if one was
+/// executed.
+fn run_script_pubkey(
+    script_pubkey: &Script,
+    stack: Stack,
+    flags: VerificationFlags,
+    ctx: Option<&TxContext>,
+    sigops: &mut usize,
+    on_step: &mut dyn FnMut(&StepInfo),
+) -> Result<(Stack, Option<ScriptBuf>), ScriptError> {
+    let redeem_script = if flags.contains(VerificationFlag::P2sh) && is_p2sh(script_pubkey) {
+        stack.top().cloned()
+    } else {
+        None
+    };
+
+    let mut stack = run_script(script_pubkey, stack, flags, ctx, SigVersion::Base, sigops, on_step)?;
+    match redeem_script {
+        Some(redeem_script) if stack.is_true() => {
+            // The P2SH script pubkey only replaces the redeem script with
+            // `true`, the rest of the stack is handed on untouched.
+            stack.pop();
+            let redeem_script = ScriptBuf::from_bytes(redeem_script);
+            let stack = run_script(&redeem_script, stack, flags, ctx, SigVersion::Base, sigops, on_step)?;
+            Ok((stack, Some(redeem_script)))
+        }
+        _ => Ok((stack, None)),
+    }
+}
+
+/// Verifies `witness` against the witness program `program`, `p2sh` is
+/// set if the program is the redeem script of a P2SH spend.
+///
+/// Unknown witness versions are valid, unless discouraged by `flags`, to
+/// allow for future soft forks. Taproot outputs can not be wrapped in
+/// P2SH so are treated as an unknown version if they are.
+fn verify_witness_program(
+    program: &Script,
+    p2sh: bool,
+    witness: &Witness,
+    flags: VerificationFlags,
+    ctx: Option<&TxContext>,
+    sigops: &mut usize,
+    on_step: &mut dyn FnMut(&StepInfo),
+) -> Result<bool, ScriptError> {
+    // A witness program is a version opcode followed by a single push.
+    let bytes = program.as_bytes();
+    let version = Opcode::from(bytes[0]);
+    let program = &bytes[2..];
+
+    match (version, program.len()) {
+        (OP_PUSHBYTES_0, 32) => {
+            let mut items = witness.iter().map(|item| item.to_vec()).collect::<Vec<_>>();
+            let witness_script = items.pop().ok_or(ScriptError::WitnessProgramWitnessEmpty)?;
+            // The consensus limit of `MAX_SCRIPT_SIZE` is checked by the interpreter.
+            if flags.contains(VerificationFlag::StandardWitnessScriptSize)
+                && witness_script.len() > MAX_STANDARD_P2WSH_SCRIPT_SIZE
+            {
+                return Err(ScriptError::WitnessScriptSize);
+            }
+            if DefaultBackend::sha256(&witness_script)[..] != *program {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            execute_witness_script(&ScriptBuf::from_bytes(witness_script), items, flags, ctx, sigops, on_step)
+        }
+        // TODO: Execute P2WPKH spends.
+        (OP_PUSHBYTES_0, 20) => Err(ScriptError::UnsupportedWitnessSpend),
+        (OP_PUSHBYTES_0, _) => Err(ScriptError::WitnessProgramWrongLength),
+        (OP_PUSHNUM_1, 32) if flags.contains(VerificationFlag::Taproot) && !p2sh => {
+            let ctx = ctx.ok_or(ScriptError::MissingTxContext)?;
+            verify_taproot_key_path(program, ctx)
+        }
+        _ => {
+            if flags.contains(VerificationFlag::DiscourageUpgradableWitnessProgram) {
+                return Err(ScriptError::DiscourageUpgradableWitnessProgram);
+            }
+            Ok(true)
+        }
+    }
+}
+
+/// Executes the segwit v0 `witness_script` starting with the witness stack `items`.
+///
+/// # Returns
+///
+/// Returns an error if the witness stack is too big or an item exceeds the
+/// maximum element size, or if execution does not leave exactly one true
+/// item on the stack.
+fn execute_witness_script(
+    witness_script: &Script,
+    items: Vec<Vec<u8>>,
+    flags: VerificationFlags,
+    ctx: Option<&TxContext>,
+    sigops: &mut usize,
+    on_step: &mut dyn FnMut(&StepInfo),
+) -> Result<bool, ScriptError> {
+    if items.len() > MAX_STACK_SIZE {
+        return Err(ScriptError::StackSize);
+    }
+    if items.iter().any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+        return Err(ScriptError::PushSize);
+    }
+
+    let stack = run_script(witness_script, Stack::from(items), flags, ctx, SigVersion::WitnessV0, sigops, on_step)?;
+    // Witness scripts implicitly require a clean stack.
+    if stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    if !stack.is_true() {
+        return Err(ScriptError::EvalFalse);
+    }
+    Ok(true)
+}
+
+/// Executes `script` starting with `stack`, returning the stack after
+/// execution and adding the number of signature operations executed to
+/// `sigops`, `on_step` is called after each instruction is executed.
+fn run_script(
+    script: &Script,
+    stack: Stack,
+    flags: VerificationFlags,
+    ctx: Option<&TxContext>,
+    sig_version: SigVersion,
+    sigops: &mut usize,
+    on_step: &mut dyn FnMut(&StepInfo),
+) -> Result<Stack, ScriptError> {
+    let mut interpreter =
+        Interpreter::new(script.to_owned()).with_flags(flags).with_sig_version(sig_version).with_stack(stack);
+    if let Some(ctx) = ctx {
+        interpreter = interpreter.with_tx_context(ctx);
+    }
+    let res = interpreter.execute_script_with_trace(on_step);
+    *sigops += interpreter.sigops();
+    res?;
+    Ok(interpreter.into_stack())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flags::{BIP16_HEIGHT, SEGWIT_HEIGHT};
+    use crate::interpreter::build_sighash;
+    use bitcoin::opcodes::all::*;
+    use bitcoin::script::PushBytesBuf;
+    use bitcoin::secp256k1::{ecdsa, Keypair, Message, PublicKey, Secp256k1, SecretKey};
+    use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+    use bitcoin::{absolute, transaction, Amount, OutPoint, Script, Sequence, Transaction, TxIn, Witness};
+
+    /// Creates a PSBT spending output 0 of a transaction paying to `script_pubkey`.
+    fn psbt_spending(script_pubkey: ScriptBuf) -> Psbt {
+        let prev_tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut { value: Amount::ONE_BTC, script_pubkey }],
+        };
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: prev_tx.compute_txid(), vout: 0 },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![],
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].non_witness_utxo = Some(prev_tx);
+        psbt
+    }
+
+    #[test]
+    fn verify_transaction_checks_every_input() {
+        let input = TxIn {
+            previous_output: OutPoint::COINBASE_PREVOUT,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        };
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![input.clone(), input],
+            output: vec![],
+        };
+        let prevout = |script_pubkey| TxOut { value: Amount::ONE_BTC, script_pubkey };
+        let valid = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+        let invalid = Script::builder().push_opcode(OP_PUSHBYTES_0).into_script();
+        let flags = VerificationFlags::CONSENSUS;
+
+        assert!(verify_transaction(&tx, &[prevout(valid.clone()), prevout(valid.clone())], flags).unwrap());
+        assert!(!verify_transaction(&tx, &[prevout(valid.clone()), prevout(invalid)], flags).unwrap());
+        assert!(verify_transaction(&tx, &[prevout(valid)], flags).is_err());
+    }
+
+    #[test]
+    fn verify_finalized_psbt_input() {
+        let script_pubkey = Script::builder()
+            .push_opcode(OP_ADD)
+            .push_int(5).unwrap()
+            .push_opcode(OP_EQUAL)
+            .into_script();
+        let script_sig = Script::builder().push_int(2).unwrap().push_int(3).unwrap().into_script();
+
+        let mut psbt = psbt_spending(script_pubkey);
+        psbt.inputs[0].final_script_sig = Some(script_sig);
+
+        assert!(verify_psbt_input(&psbt, 0).unwrap());
+    }
+
+    /// Returns the P2SH script pubkey for `redeem_script`.
+    fn p2sh(redeem_script: &Script) -> ScriptBuf {
+        Script::builder()
+            .push_opcode(OP_HASH160)
+            .push_slice(DefaultBackend::hash160(redeem_script.as_bytes()))
+            .push_opcode(OP_EQUAL)
+            .into_script()
+    }
+
+    #[test]
+    fn p2sh_redeem_script_executes() {
+        let redeem_script = Script::builder().push_opcode(OP_ADD).push_int(5).unwrap().push_opcode(OP_EQUAL).into_script();
+        let script_sig = Script::builder()
+            .push_int(2).unwrap()
+            .push_int(3).unwrap()
+            .push_slice(PushBytesBuf::try_from(redeem_script.to_bytes()).unwrap())
+            .into_script();
+
+        assert!(execute(script_sig, p2sh(&redeem_script)).unwrap());
+    }
+
+    #[test]
+    fn p2sh_handoff_moves_stack() {
+        let flags = VerificationFlags::CONSENSUS;
+        let redeem_script = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+        let script_sig = Script::builder()
+            .push_slice(PushBytesBuf::try_from(vec![0xab; 520]).unwrap())
+            .push_slice(PushBytesBuf::try_from(redeem_script.to_bytes()).unwrap())
+            .into_script();
+
+        let stack = run_script(&script_sig, Stack::new(), flags, None, SigVersion::Base, &mut 0, &mut |_| {}).unwrap();
+        let large_item = stack.items()[0].as_ptr();
+
+        let (stack, _) = run_script_pubkey(&p2sh(&redeem_script), stack, flags, None, &mut 0, &mut |_| {}).unwrap();
+        assert_eq!(stack.items().len(), 2);
+        // The large item is still in the same allocation so was not copied.
+        assert_eq!(stack.items()[0].as_ptr(), large_item);
+        assert!(stack.is_true());
+    }
+
+    #[test]
+    fn p2sh_script_sig_must_be_push_only() {
+        let redeem_script = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+        let script_sig = Script::builder()
+            .push_slice(PushBytesBuf::try_from(redeem_script.to_bytes()).unwrap())
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_DROP)
+            .into_script();
+        let flags = VerificationFlags::CONSENSUS;
+        assert!(!flags.contains(VerificationFlag::SigPushOnly));
+
+        let err = verify_script(&script_sig, &p2sh(&redeem_script), flags, None).unwrap_err();
+        assert_eq!(err, ScriptError::SigPushOnly);
+    }
+
+    #[test]
+    fn p2sh_not_evaluated_before_activation() {
+        // The redeem script leaves false on the stack.
+        let redeem_script = Script::builder().push_opcode(OP_PUSHBYTES_0).into_script();
+        let script_sig = Script::builder().push_slice(PushBytesBuf::try_from(redeem_script.to_bytes()).unwrap()).into_script();
+        let script_pubkey = p2sh(&redeem_script);
+
+        let before = VerificationFlags::mandatory_at_height(BIP16_HEIGHT - 1);
+        assert!(verify_script(&script_sig, &script_pubkey, before, None).unwrap());
+
+        let after = VerificationFlags::mandatory_at_height(BIP16_HEIGHT);
+        assert!(!verify_script(&script_sig, &script_pubkey, after, None).unwrap());
+    }
+
+    #[test]
+    fn empty_script_pubkey() {
+        let script_sig = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+        assert!(execute(script_sig, ScriptBuf::new()).unwrap());
+
+        assert!(!execute(ScriptBuf::new(), ScriptBuf::new()).unwrap());
+    }
+
+    #[test]
+    fn eval_redeem_script_fragment() {
+        let redeem_script = Script::builder().push_opcode(OP_ADD).push_int(5).unwrap().push_opcode(OP_EQUAL).into_script();
+        let mut stack = Stack::new();
+        stack.push_num(2);
+        stack.push_num(3);
+
+        assert!(eval_truthy(&redeem_script, stack).unwrap());
+    }
+
+    #[test]
+    fn trace_arithmetic_to_json() {
+        let script_sig = Script::builder().push_int(2).unwrap().push_int(3).unwrap().into_script();
+        let script_pubkey = Script::builder()
+            .push_opcode(OP_ADD)
+            .push_int(5).unwrap()
+            .push_opcode(OP_EQUAL)
+            .into_script();
+
+        let json = trace_to_json(script_sig, script_pubkey, VerificationFlags::CONSENSUS);
+        let want = concat!(
+            "[",
+            r#"{"pc":0,"opcode":"OP_PUSHNUM_2","stack":["02"]},"#,
+            r#"{"pc":1,"opcode":"OP_PUSHNUM_3","stack":["02","03"]},"#,
+            r#"{"pc":0,"opcode":"OP_ADD","stack":["05"]},"#,
+            r#"{"pc":1,"opcode":"OP_PUSHNUM_5","stack":["05","05"]},"#,
+            r#"{"pc":2,"opcode":"OP_EQUAL","stack":["01"]}"#,
+            "]",
+        );
+        assert_eq!(json, want);
+    }
+
+    #[test]
+    fn trace_p2sh_redeem_script_to_json() {
+        let redeem_script = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+        let script_sig = Script::builder().push_slice(PushBytesBuf::try_from(redeem_script.to_bytes()).unwrap()).into_script();
+        let hash = to_hex(&DefaultBackend::hash160(redeem_script.as_bytes()));
+
+        let json = trace_to_json(script_sig, p2sh(&redeem_script), VerificationFlags::CONSENSUS);
+        let want = format!(
+            concat!(
+                "[",
+                r#"{{"pc":0,"opcode":"OP_PUSHBYTES_1","stack":["51"]}},"#,
+                r#"{{"pc":0,"opcode":"OP_HASH160","stack":["{hash}"]}},"#,
+                r#"{{"pc":1,"opcode":"OP_PUSHBYTES_20","stack":["{hash}","{hash}"]}},"#,
+                r#"{{"pc":22,"opcode":"OP_EQUAL","stack":["01"]}},"#,
+                r#"{{"pc":0,"opcode":"OP_PUSHNUM_1","stack":["01"]}}"#,
+                "]",
+            ),
+            hash = hash,
+        );
+        assert_eq!(json, want);
+    }
+
+    /// Creates a context for a single input transaction spending with `witness`.
+    fn witness_context(witness: Witness) -> TxContext {
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::COINBASE_PREVOUT,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness,
+            }],
+            output: vec![],
+        };
+        TxContext::new(tx, 0).unwrap()
+    }
+
+    #[test]
+    fn p2wsh_script_pubkey_commits_to_witness_script() {
+        let witness_script = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+        let script_pubkey = p2wsh_script_pubkey(&witness_script);
+
+        assert!(script_pubkey.is_p2wsh());
+        assert_eq!(to_hex(script_pubkey.as_bytes()), "00204ae81572f06e1b88fd5ced7a1a000945432e83e1551e6f721ee9c00b8cc33260");
+    }
+
+    #[test]
+    fn p2wpkh_script_pubkey_commits_to_pubkey() {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+        let script_pubkey = p2wpkh_script_pubkey(&PublicKey::from_secret_key(&secp, &sk));
+
+        assert!(script_pubkey.is_p2wpkh());
+        assert_eq!(to_hex(script_pubkey.as_bytes()), "001479b000887626b294a914501a4cd226b58b235983");
+    }
+
+    #[test]
+    fn p2wsh_witness_script_executes() {
+        let witness_script = Script::builder().push_opcode(OP_ADD).push_int(5).unwrap().push_opcode(OP_EQUAL).into_script();
+        let mut witness = Witness::new();
+        witness.push([0x02]);
+        witness.push([0x03]);
+        witness.push(witness_script.as_bytes());
+        let ctx = witness_context(witness);
+
+        let valid = verify_script(Script::new(), &p2wsh_script_pubkey(&witness_script), VerificationFlags::CONSENSUS, Some(&ctx));
+        assert!(valid.unwrap());
+    }
+
+    #[test]
+    fn p2wsh_oversized_witness_element() {
+        let witness_script = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+        let mut witness = Witness::new();
+        witness.push(vec![0xab; MAX_SCRIPT_ELEMENT_SIZE + 1]);
+        witness.push(witness_script.as_bytes());
+        let ctx = witness_context(witness);
+
+        let err = verify_script(Script::new(), &p2wsh_script_pubkey(&witness_script), VerificationFlags::CONSENSUS, Some(&ctx))
+            .unwrap_err();
+        assert_eq!(err, ScriptError::PushSize);
+    }
+
+    #[test]
+    fn p2wsh_oversized_witness_script() {
+        // Each push and drop of a maximum size element is 524 bytes.
+        let verify = |drops, flags| {
+            let witness_script = (0..drops)
+                .fold(Script::builder(), |b, _| {
+                    b.push_slice(PushBytesBuf::try_from(vec![0xab; MAX_SCRIPT_ELEMENT_SIZE]).unwrap()).push_opcode(OP_DROP)
+                })
+                .push_opcode(OP_PUSHNUM_1)
+                .into_script();
+            let mut witness = Witness::new();
+            witness.push(witness_script.as_bytes());
+            let ctx = witness_context(witness);
+            verify_script(Script::new(), &p2wsh_script_pubkey(&witness_script), flags, Some(&ctx))
+        };
+        let flags = VerificationFlags::CONSENSUS;
+
+        assert_eq!(verify(7, flags), Ok(true));
+        assert_eq!(verify(7, flags.with(VerificationFlag::StandardWitnessScriptSize)), Err(ScriptError::WitnessScriptSize));
+        assert_eq!(verify(20, flags), Err(ScriptError::ScriptSize));
+    }
+
+    #[test]
+    fn p2wsh_witness_stack_too_big() {
+        let witness_script = ScriptBuf::new();
+        let mut witness = Witness::new();
+        for _ in 0..=MAX_STACK_SIZE {
+            witness.push(Vec::<u8>::new());
+        }
+        witness.push(witness_script.as_bytes());
+        let ctx = witness_context(witness);
+
+        let err = verify_script(Script::new(), &p2wsh_script_pubkey(&witness_script), VerificationFlags::CONSENSUS, Some(&ctx))
+            .unwrap_err();
+        assert_eq!(err, ScriptError::StackSize);
+    }
+
+    #[test]
+    fn p2wsh_witness_script_mismatch() {
+        let witness_script = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+        let other = Script::builder().push_opcode(OP_PUSHNUM_2).into_script();
+        let mut witness = Witness::new();
+        witness.push(other.as_bytes());
+        let ctx = witness_context(witness);
+
+        let err = verify_script(Script::new(), &p2wsh_script_pubkey(&witness_script), VerificationFlags::CONSENSUS, Some(&ctx))
+            .unwrap_err();
+        assert_eq!(err, ScriptError::WitnessProgramMismatch);
+    }
+
+    #[test]
+    fn p2wsh_witness_script_clean_stack() {
+        let witness_script = Script::builder().push_opcode(OP_PUSHNUM_1).push_opcode(OP_PUSHNUM_1).into_script();
+        let mut witness = Witness::new();
+        witness.push(witness_script.as_bytes());
+        let ctx = witness_context(witness);
+
+        let err = verify_script(Script::new(), &p2wsh_script_pubkey(&witness_script), VerificationFlags::CONSENSUS, Some(&ctx))
+            .unwrap_err();
+        assert_eq!(err, ScriptError::CleanStack);
+    }
+
+    #[test]
+    fn witness_program_requires_empty_script_sig() {
+        let witness_script = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+        let mut witness = Witness::new();
+        witness.push(witness_script.as_bytes());
+        let ctx = witness_context(witness);
+        let script_sig = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+
+        let err = verify_script(&script_sig, &p2wsh_script_pubkey(&witness_script), VerificationFlags::CONSENSUS, Some(&ctx))
+            .unwrap_err();
+        assert_eq!(err, ScriptError::WitnessMalleated);
+    }
+
+    #[test]
+    fn witness_for_non_witness_program() {
+        let script_pubkey = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+        let mut witness = Witness::new();
+        witness.push([0x01]);
+        let ctx = witness_context(witness);
+
+        let err = verify_script(Script::new(), &script_pubkey, VerificationFlags::CONSENSUS, Some(&ctx)).unwrap_err();
+        assert_eq!(err, ScriptError::WitnessUnexpected);
+    }
+
+    #[test]
+    fn witness_v0_program_wrong_length() {
+        let script_pubkey = Script::builder()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_slice(PushBytesBuf::try_from(vec![0xab; 21]).unwrap())
+            .into_script();
+        let ctx = witness_context(Witness::new());
+
+        let err = verify_script(Script::new(), &script_pubkey, VerificationFlags::CONSENSUS, Some(&ctx)).unwrap_err();
+        assert_eq!(err, ScriptError::WitnessProgramWrongLength);
+    }
+
+    #[test]
+    fn upgradable_witness_program() {
+        let script_pubkey = Script::builder().push_opcode(OP_PUSHNUM_2).push_slice([0xab; 32]).into_script();
+        let ctx = witness_context(Witness::new());
+
+        let flags = VerificationFlags::CONSENSUS;
+        assert_eq!(verify_script(Script::new(), &script_pubkey, flags, Some(&ctx)), Ok(true));
+
+        let flags = flags.with(VerificationFlag::DiscourageUpgradableWitnessProgram);
+        let err = verify_script(Script::new(), &script_pubkey, flags, Some(&ctx)).unwrap_err();
+        assert_eq!(err, ScriptError::DiscourageUpgradableWitnessProgram);
+    }
+
+    #[test]
+    fn p2sh_multisig_executed_sigops() {
+        // A 0-of-3 multisig needs no signatures but still counts three sigops.
+        let redeem_script = Script::builder()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_slice([0x02; 33])
+            .push_slice([0x03; 33])
+            .push_slice([0x02; 33])
+            .push_opcode(OP_PUSHNUM_3)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let script_sig = Script::builder()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_slice(PushBytesBuf::try_from(redeem_script.to_bytes()).unwrap())
+            .into_script();
+        let mut tx = witness_context(Witness::new()).tx().clone();
+        tx.input[0].script_sig = script_sig;
+        let ctx = TxContext::new(tx, 0).unwrap();
+
+        let sigops = verify_input_with_sigops(&p2sh(&redeem_script), VerificationFlags::CONSENSUS, &ctx).unwrap();
+        // The accurate P2SH sigop count uses the public key count pushed before OP_CHECKMULTISIG.
+        assert_eq!(sigops, 3);
+    }
+
+    #[test]
+    fn witness_verified_after_segwit_activation() {
+        let witness_script = Script::builder().push_opcode(OP_ADD).push_int(5).unwrap().push_opcode(OP_EQUAL).into_script();
+        let spend = |a: u8, b: u8| {
+            let mut witness = Witness::new();
+            witness.push([a]);
+            witness.push([b]);
+            witness.push(witness_script.as_bytes());
+            witness_context(witness).tx().clone()
+        };
+        let prevouts = [TxOut { value: Amount::ONE_BTC, script_pubkey: p2wsh_script_pubkey(&witness_script) }];
+
+        let valid = spend(2, 3);
+        assert!(verify_input_at_height(&valid, &prevouts, 0, SEGWIT_HEIGHT).unwrap());
+        assert!(verify_input_at_height(&valid, &prevouts, 0, SEGWIT_HEIGHT - 1).unwrap());
+
+        // Before activation the witness is not verified so anyone can spend.
+        let invalid = spend(2, 2);
+        let err = verify_input_at_height(&invalid, &prevouts, 0, SEGWIT_HEIGHT).unwrap_err();
+        assert_eq!(err.downcast_ref::<ScriptError>(), Some(&ScriptError::EvalFalse));
+        assert!(verify_input_at_height(&invalid, &prevouts, 0, SEGWIT_HEIGHT - 1).unwrap());
+    }
+
+    /// Returns a signature, with sighash type `ALL`, of the input in `ctx`
+    /// by the secret key `[0x01; 32]`.
+    fn sign(ctx: &TxContext, script_code: &Script, sig_version: SigVersion) -> Vec<u8> {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+        let msg = build_sighash(ctx, script_code, 0x01, sig_version).unwrap();
+        let mut sig = secp.sign_ecdsa(&msg, &sk).serialize_der().to_vec();
+        sig.push(0x01);
+        sig
+    }
+
+    /// Returns `sig` with S replaced by `n - S`, the equivalent high S signature.
+    fn high_s(sig: &ecdsa::Signature) -> ecdsa::Signature {
+        // The order of the secp256k1 curve.
+        let n: [u8; 32] = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+            0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b,
+            0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+        ];
+        let mut compact = sig.serialize_compact();
+        let mut borrow = 0;
+        for (i, n_byte) in n.iter().enumerate().rev() {
+            let diff = i16::from(*n_byte) - i16::from(compact[32 + i]) - borrow;
+            compact[32 + i] = diff.rem_euclid(256) as u8;
+            borrow = i16::from(diff < 0);
+        }
+        ecdsa::Signature::from_compact(&compact).unwrap()
+    }
+
+    #[test]
+    fn high_s_only_sensitive_to_low_s() {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+        let script_pubkey = Script::builder()
+            .push_slice(PublicKey::from_secret_key(&secp, &sk).serialize())
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let ctx = witness_context(Witness::new());
+        let msg = build_sighash(&ctx, &script_pubkey, 0x01, SigVersion::Base).unwrap();
+        let mut sig = high_s(&secp.sign_ecdsa(&msg, &sk)).serialize_der().to_vec();
+        sig.push(0x01);
+        let script_sig = Script::builder().push_slice(PushBytesBuf::try_from(sig).unwrap()).into_script();
+
+        let flags = flag_sensitivity(&script_sig, &script_pubkey, Some(&ctx)).unwrap();
+        assert_eq!(flags, vec![(VerificationFlag::LowS, false)]);
+
+        assert_eq!(flag_sensitivity(&script_sig, &script_pubkey, None), Err(ScriptError::MissingTxContext));
+    }
+
+    /// Returns the uncompressed public key for the secret key `[0x01; 32]`.
+    fn uncompressed_pubkey() -> [u8; 65] {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+        PublicKey::from_secret_key(&secp, &sk).serialize_uncompressed()
+    }
+
+    #[test]
+    fn witness_uncompressed_pubkey() {
+        let witness_script = Script::builder().push_slice(uncompressed_pubkey()).push_opcode(OP_CHECKSIG).into_script();
+        let prevouts = vec![TxOut { value: Amount::ONE_BTC, script_pubkey: p2wsh_script_pubkey(&witness_script) }];
+        let unsigned = witness_context(Witness::new()).with_prevouts(prevouts.clone()).unwrap();
+
+        let mut witness = Witness::new();
+        witness.push(sign(&unsigned, &witness_script, SigVersion::WitnessV0));
+        witness.push(witness_script.as_bytes());
+        let ctx = witness_context(witness).with_prevouts(prevouts.clone()).unwrap();
+
+        let flags = VerificationFlags::CONSENSUS;
+        assert!(verify_script(Script::new(), &prevouts[0].script_pubkey, flags, Some(&ctx)).unwrap());
+
+        let flags = flags.with(VerificationFlag::WitnessPubkeyType);
+        let err = verify_script(Script::new(), &prevouts[0].script_pubkey, flags, Some(&ctx)).unwrap_err();
+        assert_eq!(err, ScriptError::WitnessPubkeyType);
+    }
+
+    #[test]
+    fn legacy_uncompressed_pubkey() {
+        let script_pubkey = Script::builder().push_slice(uncompressed_pubkey()).push_opcode(OP_CHECKSIG).into_script();
+        let unsigned = witness_context(Witness::new());
+        let sig = sign(&unsigned, &script_pubkey, SigVersion::Base);
+        let script_sig = Script::builder().push_slice(PushBytesBuf::try_from(sig).unwrap()).into_script();
+
+        let flags = VerificationFlags::CONSENSUS.with(VerificationFlag::WitnessPubkeyType);
+        assert!(verify_script(&script_sig, &script_pubkey, flags, Some(&unsigned)).unwrap());
+    }
+
+    #[test]
+    fn tapscript_2_of_3_checksigadd() {
+        use bitcoin::taproot::{LeafVersion, TapLeafHash};
+
+        let secp = Secp256k1::new();
+        let keypairs = [[0x01; 32], [0x02; 32], [0x03; 32]]
+            .map(|sk| Keypair::from_seckey_slice(&secp, &sk).unwrap());
+        let pubkeys = keypairs.map(|keypair| keypair.x_only_public_key().0.serialize());
+        let leaf_script = Script::builder()
+            .push_slice(pubkeys[0])
+            .push_opcode(OP_CHECKSIG)
+            .push_slice(pubkeys[1])
+            .push_opcode(OP_CHECKSIGADD)
+            .push_slice(pubkeys[2])
+            .push_opcode(OP_CHECKSIGADD)
+            .push_int(2).unwrap()
+            .push_opcode(OP_NUMEQUAL)
+            .into_script();
+
+        let prevouts = vec![TxOut { value: Amount::ONE_BTC, script_pubkey: ScriptBuf::new() }];
+        let ctx = witness_context(Witness::new()).with_prevouts(prevouts.clone()).unwrap();
+        let leaf_hash = TapLeafHash::from_script(&leaf_script, LeafVersion::TapScript);
+        let sighash = SighashCache::new(ctx.tx())
+            .taproot_signature_hash(0, &Prevouts::All(&prevouts), None, Some((leaf_hash, u32::MAX)), TapSighashType::Default)
+            .unwrap();
+        let msg = Message::from_digest(sighash.to_byte_array());
+        let sign = |keypair: &Keypair| secp.sign_schnorr_no_aux_rand(&msg, keypair).serialize().to_vec();
+
+        // The signature for the first key is on top, the second key does not sign.
+        let witness_stack = vec![sign(&keypairs[2]), vec![], sign(&keypairs[0])];
+        let res = execute_tapscript(&leaf_script, witness_stack, 1000, VerificationFlags::CONSENSUS, &ctx).unwrap();
+        assert!(res.result.unwrap());
+
+        let witness_stack = vec![vec![], vec![], sign(&keypairs[0])];
+        let res = execute_tapscript(&leaf_script, witness_stack, 1000, VerificationFlags::CONSENSUS, &ctx).unwrap();
+        assert!(!res.result.unwrap());
+    }
+
+    #[test]
+    fn taproot_key_path_spend() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[0x01; 32]).unwrap();
+        let output_key = keypair.x_only_public_key().0.serialize();
+        let script_pubkey = Script::builder().push_opcode(OP_PUSHNUM_1).push_slice(output_key).into_script();
+        let prevouts = vec![TxOut { value: Amount::ONE_BTC, script_pubkey: script_pubkey.clone() }];
+
+        // The signature hash does not commit to the witness.
+        let ctx = witness_context(Witness::new());
+        let sighash = SighashCache::new(ctx.tx())
+            .taproot_signature_hash(0, &Prevouts::All(&prevouts), None, None, TapSighashType::Default)
+            .unwrap();
+        let sig = secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), &keypair);
+        let verify = |sig: &[u8]| {
+            let mut witness = Witness::new();
+            witness.push(sig);
+            let ctx = witness_context(witness).with_prevouts(prevouts.clone()).unwrap();
+            verify_script(Script::new(), &script_pubkey, VerificationFlags::CONSENSUS, Some(&ctx))
+        };
+
+        assert_eq!(verify(&sig.serialize()), Ok(true));
+        let mut wrong_sig = sig.serialize();
+        wrong_sig[0] ^= 0x01;
+        assert_eq!(verify(&wrong_sig), Err(ScriptError::SchnorrSig));
+        assert_eq!(verify(&wrong_sig[..63]), Err(ScriptError::SchnorrSigSize));
+    }
+
+    #[test]
+    fn verify_signed_p2wsh_psbt_input() {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[0x01; 32]).unwrap();
+        let witness_script = Script::builder()
+            .push_slice(PublicKey::from_secret_key(&secp, &sk).serialize())
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let mut psbt = psbt_spending(p2wsh_script_pubkey(&witness_script));
+        let prev_tx = psbt.inputs[0].non_witness_utxo.take().unwrap();
+        psbt.inputs[0].witness_utxo = Some(prev_tx.output[0].clone());
+
+        // The segwit v0 signature hash commits to the amount of the spent output.
+        let unsigned = TxContext::new(psbt.unsigned_tx.clone(), 0).unwrap().with_prevouts(prev_tx.output).unwrap();
+        let mut witness = Witness::new();
+        witness.push(sign(&unsigned, &witness_script, SigVersion::WitnessV0));
+        witness.push(witness_script.as_bytes());
+        psbt.inputs[0].final_script_witness = Some(witness);
+
+        assert!(verify_psbt_input(&psbt, 0).unwrap());
+    }
+
+    #[test]
+    fn verify_non_finalized_psbt_input() {
+        let script_pubkey = Script::builder().push_opcode(OP_PUSHNUM_1).into_script();
+        let psbt = psbt_spending(script_pubkey);
+
+        assert!(verify_psbt_input(&psbt, 0).is_err());
+    }
 }
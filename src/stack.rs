@@ -1,8 +1,29 @@
 // SPDX-License-Identifier: CC0-1.0
 
-use anyhow::{anyhow, Result};
+//! Provides the stack used during script execution.
+
 use bitcoin::script;
 
+use crate::error::ScriptError;
+
+/// The maximum size in bytes of a number used as an operand to the
+/// arithmetic opcodes.
+///
+/// The result of an arithmetic opcode can be bigger e.g., adding two 4
+/// byte numbers can give a 5 byte number. The result is pushed as such
+/// and compared byte for byte by `OP_EQUAL`, but is rejected if used as
+/// an operand.
+pub const MAX_NUM_SIZE: usize = 4;
+
+/// Returns `true` if `bytes` is true when interpreted as a boolean.
+///
+/// Any non-zero byte makes `bytes` true, except for the sign bit alone
+/// in the last byte (negative zero). This is the rule used by `OP_IF`,
+/// `OP_VERIFY` and `OP_NOT`, and for the top item after execution.
+pub fn is_truthy(bytes: &[u8]) -> bool {
+    script::read_scriptbool(bytes)
+}
+
 /// The stack used during script execution.
 pub struct Stack {
     // FIXME: I can't work out how to separate items on the stack
@@ -25,6 +46,9 @@ impl Stack {
     }
 
     /// Pushes number onto the stack.
+    ///
+    /// Unlike the numbers popped, `x` is not limited to [`MAX_NUM_SIZE`]
+    /// bytes so that the result of arithmetic can be pushed.
     pub fn push_num(&mut self, x: i64) {
         let mut buf = [0_u8; 8];
         let written = script::write_scriptint(&mut buf, x);
@@ -41,33 +65,223 @@ impl Stack {
     }
 
     /// Pops the top item from the stack.
-    #[allow(dead_code)]
     pub fn pop(&mut self) -> Option<Vec<u8>> {
         self.items.pop()
     }
 
+    /// Pops the top item from the stack.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if stack is empty.
+    pub fn pop_bytes(&mut self) -> Result<Vec<u8>, ScriptError> {
+        self.items.pop().ok_or(ScriptError::InvalidStackOperation)
+    }
+
     /// Pops the top item from the stack returning it if it is a scriptint.
     ///
     /// # Returns
     ///
-    /// Returns an error if stack is empty or scriptint parsing fails.
-    pub fn pop_num(&mut self) -> Result<i64> {
-        let item = self.items.pop().ok_or_else(|| anyhow!("called pop on an empty stack"))?;
-        Ok(script::read_scriptint_non_minimal(&item)?)
+    /// Returns an error if stack is empty or the top item is longer than
+    /// [`MAX_NUM_SIZE`] bytes.
+    pub fn pop_num(&mut self) -> Result<i64, ScriptError> {
+        let item = self.items.pop().ok_or(ScriptError::InvalidStackOperation)?;
+        read_num(&item, MAX_NUM_SIZE)
+    }
+
+    /// Returns the top item from the stack, without removing it, if it is a scriptint.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if stack is empty or the top item is longer than
+    /// [`MAX_NUM_SIZE`] bytes.
+    pub fn top_num(&self) -> Result<i64, ScriptError> {
+        self.top_num_with_max_size(MAX_NUM_SIZE)
+    }
+
+    /// Returns the top item from the stack, without removing it, if it is
+    /// a scriptint of at most `max_size` bytes.
+    ///
+    /// Used by `OP_CHECKLOCKTIMEVERIFY` and `OP_CHECKSEQUENCEVERIFY` which
+    /// accept 5 byte numbers to cover the full range of a `u32`.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if stack is empty, or if the top item is too long.
+    /// Numbers of more than 8 bytes do not fit an `i64` so if `max_size`
+    /// is more than 8 this fails as if it were 8.
+    pub fn top_num_with_max_size(&self, max_size: usize) -> Result<i64, ScriptError> {
+        let max_size = max_size.min(8);
+        let item = self.top().ok_or(ScriptError::InvalidStackOperation)?;
+        read_num(item, max_size)
+    }
+
+    /// Removes the top item (`OP_DROP`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the stack is empty.
+    pub fn drop(&mut self) -> Result<(), ScriptError> {
+        self.pop_bytes()?;
+        Ok(())
+    }
+
+    /// Removes the top two items (`OP_2DROP`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are less than two items on the stack.
+    pub fn drop2(&mut self) -> Result<(), ScriptError> {
+        self.peek(1)?;
+        self.items.truncate(self.items.len() - 2);
+        Ok(())
+    }
+
+    /// Removes the second to top item (`OP_NIP`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are less than two items on the stack.
+    pub fn nip(&mut self) -> Result<(), ScriptError> {
+        self.peek(1)?;
+        self.items.remove(self.items.len() - 2);
+        Ok(())
+    }
+
+    /// Duplicates the top item (`OP_DUP`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the stack is empty.
+    pub fn dup(&mut self) -> Result<(), ScriptError> {
+        self.pick(0)
+    }
+
+    /// Duplicates the top item if it is true (`OP_IFDUP`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the stack is empty.
+    pub fn if_dup(&mut self) -> Result<(), ScriptError> {
+        if script::read_scriptbool(self.peek(0)?) {
+            self.pick(0)?;
+        }
+        Ok(())
+    }
+
+    /// Duplicates the top two items (`OP_2DUP`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are less than two items on the stack.
+    pub fn dup2(&mut self) -> Result<(), ScriptError> {
+        self.peek(1)?;
+        self.pick(1)?;
+        self.pick(1)
+    }
+
+    /// Duplicates the top three items (`OP_3DUP`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are less than three items on the stack.
+    pub fn dup3(&mut self) -> Result<(), ScriptError> {
+        self.peek(2)?;
+        self.pick(2)?;
+        self.pick(2)?;
+        self.pick(2)
+    }
+
+    /// Swaps the top two items (`OP_SWAP`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are less than two items on the stack.
+    pub fn swap(&mut self) -> Result<(), ScriptError> {
+        self.peek(1)?;
+        let len = self.items.len();
+        self.items.swap(len - 1, len - 2);
+        Ok(())
+    }
+
+    /// Moves the third to top item to the top (`OP_ROT`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are less than three items on the stack.
+    pub fn rot(&mut self) -> Result<(), ScriptError> {
+        self.roll(2)
+    }
+
+    /// Copies the second to top item to the top (`OP_OVER`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are less than two items on the stack.
+    pub fn over(&mut self) -> Result<(), ScriptError> {
+        self.pick(1)
+    }
+
+    /// Copies the top item to below the second to top item (`OP_TUCK`).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are less than two items on the stack.
+    pub fn tuck(&mut self) -> Result<(), ScriptError> {
+        let top = self.peek(0)?.clone();
+        self.peek(1)?;
+        let index = self.items.len() - 2;
+        self.items.insert(index, top);
+        Ok(())
+    }
+
+    /// Copies the item `n` places down the stack to the top, `0` being the top item.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are not more than `n` items on the stack.
+    pub fn pick(&mut self, n: usize) -> Result<(), ScriptError> {
+        let item = self.peek(n)?.clone();
+        self.items.push(item);
+        Ok(())
+    }
+
+    /// Moves the item `n` places down the stack to the top, `0` being the top item.
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if there are not more than `n` items on the stack.
+    pub fn roll(&mut self, n: usize) -> Result<(), ScriptError> {
+        self.peek(n)?;
+        let item = self.items.remove(self.items.len() - 1 - n);
+        self.items.push(item);
+        Ok(())
+    }
+
+    /// Returns the item `n` places down the stack, `0` being the top item.
+    fn peek(&self, n: usize) -> Result<&Vec<u8>, ScriptError> {
+        let len = self.items.len();
+        if n >= len {
+            return Err(ScriptError::InvalidStackOperation);
+        }
+        Ok(&self.items[len - 1 - n])
     }
 
     /// Returns the number of items on the stack.
-    #[allow(dead_code)]
     pub fn len(&self) -> usize {
         self.items.len()
     }
 
     /// Returns true if the stack is empty.
-    #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.items.is_empty()
     }
 
+    /// Returns the items on the stack, top item last.
+    pub fn items(&self) -> &[Vec<u8>] {
+        &self.items
+    }
+
     /// Peeks at the top item without removing it.
     pub fn top(&self) -> Option<&Vec<u8>> {
         self.items.last()
@@ -78,10 +292,52 @@ impl Stack {
     /// This is the definition of valid for a Bitcoin script after execution.
     pub fn is_true(&self) -> bool {
         match self.top() {
-            Some(top) => script::read_scriptbool(&top),
+            Some(top) => is_truthy(top),
             None => false,
         }
     }
+
+    /// Checks the stack left after execution is valid.
+    ///
+    /// Unlike [`Self::is_true`] this distinguishes between the ways the
+    /// final stack can be invalid, if `clean_stack` is set exactly one
+    /// item must remain.
+    ///
+    /// # Returns
+    ///
+    /// Returns [`ScriptError::EvalFalse`] if the stack is empty or the top
+    /// item is false, and [`ScriptError::CleanStack`] if `clean_stack` is
+    /// set and more than one item remains.
+    pub fn final_validity(&self, clean_stack: bool) -> Result<bool, ScriptError> {
+        if !self.is_true() {
+            return Err(ScriptError::EvalFalse);
+        }
+        if clean_stack && self.len() != 1 {
+            return Err(ScriptError::CleanStack);
+        }
+        Ok(true)
+    }
+}
+
+/// Reads `item` as a scriptint of at most `max_size` bytes.
+///
+/// Non-minimal encodings are accepted, `max_size` must be at most 8.
+fn read_num(item: &[u8], max_size: usize) -> Result<i64, ScriptError> {
+    if item.len() > max_size {
+        return Err(ScriptError::InvalidScriptInt);
+    }
+    let last = match item.last() {
+        Some(last) => *last,
+        None => return Ok(0),
+    };
+
+    let mut n = item.iter().enumerate().fold(0_i64, |n, (i, b)| n | i64::from(*b) << (8 * i));
+    // The most significant bit of the last byte is the sign bit.
+    if last & 0x80 != 0 {
+        n &= !(0x80_i64 << (8 * (item.len() - 1)));
+        n = -n;
+    }
+    Ok(n)
 }
 
 impl Default for Stack {
@@ -89,3 +345,71 @@ impl Default for Stack {
         Self::new()
     }
 }
+
+impl From<Vec<Vec<u8>>> for Stack {
+    /// Creates a stack from `items`, the last item is the top of the stack.
+    fn from(items: Vec<Vec<u8>>) -> Self {
+        Self { items }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truthy() {
+        assert!(!is_truthy(&[]));
+        assert!(!is_truthy(&[0x00]));
+        assert!(!is_truthy(&[0x80]));
+        assert!(!is_truthy(&[0x00, 0x00, 0x80]));
+        assert!(is_truthy(&[0x01]));
+        assert!(is_truthy(&[0x80, 0x00]));
+        assert!(is_truthy(&[0x00, 0x01, 0x00]));
+    }
+
+    #[test]
+    fn pop_num_rejects_five_bytes() {
+        let mut stack = Stack::from(vec![vec![0xff, 0xff, 0xff, 0xff, 0x00], vec![0xff, 0xff, 0xff, 0x7f]]);
+
+        assert_eq!(stack.pop_num().unwrap(), i64::from(i32::MAX));
+        assert!(stack.pop_num().is_err());
+    }
+
+    #[test]
+    fn top_num_max_size_fits_i64() {
+        let stack = Stack::from(vec![vec![0x01; 9]]);
+
+        assert_eq!(stack.top_num_with_max_size(9), Err(ScriptError::InvalidScriptInt));
+        assert_eq!(stack.top_num_with_max_size(8), Err(ScriptError::InvalidScriptInt));
+    }
+
+    #[test]
+    fn final_validity_one_true_item() {
+        let stack = Stack::from(vec![vec![0x01]]);
+
+        assert_eq!(stack.final_validity(true), Ok(true));
+    }
+
+    #[test]
+    fn final_validity_one_false_item() {
+        let stack = Stack::from(vec![vec![]]);
+
+        assert_eq!(stack.final_validity(true), Err(ScriptError::EvalFalse));
+    }
+
+    #[test]
+    fn final_validity_empty_stack() {
+        let stack = Stack::new();
+
+        assert_eq!(stack.final_validity(false), Err(ScriptError::EvalFalse));
+    }
+
+    #[test]
+    fn final_validity_multiple_items() {
+        let stack = Stack::from(vec![vec![0x01], vec![0x01]]);
+
+        assert_eq!(stack.final_validity(false), Ok(true));
+        assert_eq!(stack.final_validity(true), Err(ScriptError::CleanStack));
+    }
+}
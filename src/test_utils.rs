@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Provides helpers for testing scripts, enabled by the `test-utils` feature.
+
+use bitcoin::Script;
+
+use crate::error::ScriptError;
+use crate::interpreter::Interpreter;
+use crate::stack::Stack;
+
+/// The maximum number of items on a generated stack.
+const MAX_ITEMS: u64 = 4;
+
+/// The maximum size in bytes of an item on a generated stack.
+const MAX_ITEM_SIZE: u64 = 5;
+
+/// Returns `true` if `a` and `b` behave the same when executed against
+/// `samples` pseudo-randomly generated initial stacks.
+///
+/// The scripts behave the same if both succeed with the same final
+/// stack, or both fail with the same error. Useful to check a refactor
+/// of a script does not change its behaviour.
+///
+/// The stacks are generated from a fixed seed so the result is the same
+/// every time.
+pub fn scripts_equivalent(a: &Script, b: &Script, samples: usize) -> bool {
+    let mut rng = XorShift(0x5eed_5eed_5eed_5eed);
+    (0..samples).all(|_| {
+        let items = rng.stack();
+        execute(a, items.clone()) == execute(b, items)
+    })
+}
+
+/// Decodes the hex string `hex`.
+///
+/// # Panics
+///
+/// If `hex` is not valid hex.
+pub fn hex_to_bytes(hex: &str) -> Vec<u8> {
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+}
+
+/// Executes `script` starting with `items`.
+///
+/// # Returns
+///
+/// The final stack if the script succeeds, otherwise the error.
+fn execute(script: &Script, items: Vec<Vec<u8>>) -> Result<Vec<Vec<u8>>, ScriptError> {
+    let mut interpreter = Interpreter::new(script.to_owned());
+    interpreter.execute_script_with_stack(Stack::from(items))?;
+    Ok(interpreter.into_stack().items().to_vec())
+}
+
+/// A xorshift pseudo-random number generator, good enough to generate
+/// test inputs without a dependency.
+struct XorShift(u64);
+
+impl XorShift {
+    /// Returns the next pseudo-random number.
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a stack of up to `MAX_ITEMS` items of up to `MAX_ITEM_SIZE` bytes.
+    fn stack(&mut self) -> Vec<Vec<u8>> {
+        let len = self.next_u64() % (MAX_ITEMS + 1);
+        (0..len)
+            .map(|_| {
+                let size = self.next_u64() % (MAX_ITEM_SIZE + 1);
+                (0..size).map(|_| self.next_u64() as u8).collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::opcodes::all::*;
+
+    #[test]
+    fn equivalent_scripts() {
+        let a = Script::builder().push_int(2).unwrap().push_opcode(OP_ADD).into_script();
+        let b = Script::builder()
+            .push_int(1).unwrap()
+            .push_int(1).unwrap()
+            .push_opcode(OP_ADD)
+            .push_opcode(OP_ADD)
+            .into_script();
+
+        assert!(scripts_equivalent(&a, &b, 1000));
+    }
+
+    #[test]
+    fn different_scripts() {
+        let a = Script::builder().push_int(2).unwrap().push_opcode(OP_ADD).into_script();
+        let b = Script::builder().push_int(3).unwrap().push_opcode(OP_ADD).into_script();
+
+        assert!(!scripts_equivalent(&a, &b, 1000));
+    }
+}
@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Regression tests for inputs that crashed, or are known to be problem
+//! areas for, the interpreter.
+//!
+//! Each file in `tests/regressions/` is a raw script pubkey, as saved by
+//! the fuzzer, that is executed with an empty script sig.
+
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+use bitcoin::ScriptBuf;
+
+#[test]
+fn regressions_do_not_panic() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("regressions");
+    for entry in fs::read_dir(dir).expect("regressions directory") {
+        let path = entry.expect("directory entry").path();
+        let script_pubkey = ScriptBuf::from_bytes(fs::read(&path).expect("corpus file"));
+
+        let res = panic::catch_unwind(|| interpreter::execute(ScriptBuf::new(), script_pubkey));
+        assert!(res.is_ok(), "panic executing {}", path.display());
+    }
+}